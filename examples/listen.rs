@@ -40,7 +40,7 @@ fn main() {
                 "[{}] {}",
                 Utc::now().to_rfc3339(),
                 match event {
-                    Event::Lightning(lightning) => format!(
+                    Event::Lightning(lightning, _) => format!(
                         "Lightning detected: {}.",
                         match lightning {
                             HeadOfStormDistance::Kilometers(km) => format!("{} km", km),
@@ -50,6 +50,7 @@ fn main() {
                     ),
                     Event::Noise => String::from("Noise detected."),
                     Event::Disturbance => String::from("Disturber detected."),
+                    Event::Status(status) => format!("Status: {:?}.", status),
                 }
             )
         }
@@ -0,0 +1,400 @@
+use crate::{Event, HeadOfStormDistance};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long it takes to give the all clear after the last qualifying lightning (the "30" of the 30/30 rule).
+pub const DEFAULT_ALL_CLEAR_PERIOD: Duration = Duration::from_secs(30 * 60);
+/// How long the sensor is considered degraded after the last disturber or noise event.
+pub const DEFAULT_DEGRADED_PERIOD: Duration = Duration::from_secs(60);
+pub const DEFAULT_WARNING_DISTANCE: u8 = 20;
+pub const DEFAULT_DANGER_DISTANCE: u8 = 10;
+
+/// How often [`AlertEngine::watch`] re-evaluates its countdowns when no events arrive.
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Source of time for the [`AlertEngine`], replaceable so that the state machine can be driven by a fake clock.
+pub trait Clock: Send {
+    fn now(&self) -> Instant;
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AlertLevel {
+    AllClear,
+    /// Lightning was detected, but farther than the warning distance or out of range.
+    Watch,
+    Warning,
+    Danger,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AlertState {
+    pub level: AlertLevel,
+    /// The sensor is reporting disturbers or noise and may be missing lightning.
+    pub degraded: bool,
+    /// Time left until the all clear, if there is an alert in progress.
+    pub all_clear_in: Option<Duration>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct AlertParameters {
+    pub(crate) warning_distance: u8,
+    pub(crate) danger_distance: u8,
+    pub(crate) all_clear_period: Duration,
+    pub(crate) degraded_period: Duration,
+}
+
+impl Default for AlertParameters {
+    fn default() -> Self {
+        Self {
+            warning_distance: DEFAULT_WARNING_DISTANCE,
+            danger_distance: DEFAULT_DANGER_DISTANCE,
+            all_clear_period: DEFAULT_ALL_CLEAR_PERIOD,
+            degraded_period: DEFAULT_DEGRADED_PERIOD,
+        }
+    }
+}
+
+impl AlertParameters {
+    /// Lightning at or within this distance (in kilometers) raises a warning.
+    pub fn with_warning_distance(mut self, kilometers: u8) -> Self {
+        self.warning_distance = kilometers;
+        self
+    }
+
+    /// Lightning at or within this distance (in kilometers) raises a danger alert.
+    /// Overhead lightning is always considered dangerous.
+    pub fn with_danger_distance(mut self, kilometers: u8) -> Self {
+        self.danger_distance = kilometers;
+        self
+    }
+
+    pub fn with_all_clear_period(mut self, all_clear_period: Duration) -> Self {
+        self.all_clear_period = all_clear_period;
+        self
+    }
+
+    pub fn with_degraded_period(mut self, degraded_period: Duration) -> Self {
+        self.degraded_period = degraded_period;
+        self
+    }
+
+    fn validate(&self) -> ::std::result::Result<(), &'static str> {
+        if self.danger_distance > self.warning_distance {
+            return Err("Danger distance must not be larger than warning distance");
+        }
+
+        Ok(())
+    }
+}
+
+/// Turns the stream of sensor events into lightning safety alerts.
+///
+/// Every lightning within the warning zone restarts the all clear countdown; the alert level is the most severe
+/// zone which had lightning within the last all clear period. Lightning beyond the warning zone only keeps a
+/// watch for the same period.
+pub struct AlertEngine<C: Clock = SystemClock> {
+    parameters: AlertParameters,
+    clock: C,
+    last_watch: Option<Instant>,
+    last_warning: Option<Instant>,
+    last_danger: Option<Instant>,
+    last_degradation: Option<Instant>,
+    state: AlertState,
+}
+
+impl AlertEngine<SystemClock> {
+    pub fn new(parameters: AlertParameters) -> ::std::result::Result<Self, &'static str> {
+        Self::with_clock(parameters, SystemClock)
+    }
+}
+
+impl<C: Clock> AlertEngine<C> {
+    pub fn with_clock(
+        parameters: AlertParameters,
+        clock: C,
+    ) -> ::std::result::Result<Self, &'static str> {
+        parameters.validate()?;
+
+        Ok(Self {
+            parameters,
+            clock,
+            last_watch: None,
+            last_warning: None,
+            last_danger: None,
+            last_degradation: None,
+            state: AlertState {
+                level: AlertLevel::AllClear,
+                degraded: false,
+                all_clear_in: None,
+            },
+        })
+    }
+
+    pub fn state(&self) -> AlertState {
+        self.state
+    }
+
+    /// Feeds an event into the state machine, returns the new state if the alert level or degradation changed.
+    pub fn process(&mut self, event: &Event) -> Option<AlertState> {
+        let now = self.clock.now();
+
+        match event {
+            Event::Lightning(distance, _) => {
+                let zone = self.zone(*distance);
+
+                self.last_watch = Some(now);
+                if zone >= AlertLevel::Warning {
+                    self.last_warning = Some(now);
+                }
+                if zone == AlertLevel::Danger {
+                    self.last_danger = Some(now);
+                }
+            }
+            Event::Disturbance | Event::Noise => self.last_degradation = Some(now),
+            Event::Status(_) => {}
+        }
+
+        self.evaluate(now)
+    }
+
+    /// Re-evaluates the countdowns, returns the new state if the alert level or degradation changed.
+    pub fn update(&mut self) -> Option<AlertState> {
+        let now = self.clock.now();
+
+        self.evaluate(now)
+    }
+
+    fn zone(&self, distance: HeadOfStormDistance) -> AlertLevel {
+        match distance {
+            HeadOfStormDistance::Overhead => AlertLevel::Danger,
+            HeadOfStormDistance::Kilometers(km) if km <= self.parameters.danger_distance => {
+                AlertLevel::Danger
+            }
+            HeadOfStormDistance::Kilometers(km) if km <= self.parameters.warning_distance => {
+                AlertLevel::Warning
+            }
+            HeadOfStormDistance::Kilometers(_) | HeadOfStormDistance::OutOfRange => {
+                AlertLevel::Watch
+            }
+        }
+    }
+
+    fn evaluate(&mut self, now: Instant) -> Option<AlertState> {
+        let remaining = |since: Option<Instant>, period: Duration| {
+            since
+                .map(|since| now.saturating_duration_since(since))
+                .filter(|elapsed| *elapsed < period)
+                .map(|elapsed| period - elapsed)
+        };
+
+        let all_clear_in = remaining(self.last_warning, self.parameters.all_clear_period);
+        let level = if remaining(self.last_danger, self.parameters.all_clear_period).is_some() {
+            AlertLevel::Danger
+        } else if all_clear_in.is_some() {
+            AlertLevel::Warning
+        } else if remaining(self.last_watch, self.parameters.all_clear_period).is_some() {
+            AlertLevel::Watch
+        } else {
+            AlertLevel::AllClear
+        };
+        let degraded = remaining(self.last_degradation, self.parameters.degraded_period).is_some();
+
+        let changed = level != self.state.level || degraded != self.state.degraded;
+
+        self.state = AlertState {
+            level,
+            degraded,
+            all_clear_in,
+        };
+
+        if changed {
+            info!("alert state changed to {:?}", self.state);
+            Some(self.state)
+        } else {
+            None
+        }
+    }
+}
+
+impl<C: Clock + 'static> AlertEngine<C> {
+    /// Consumes the events in a background thread and returns a channel of alert state changes.
+    /// The thread finishes once either of the channels is closed.
+    pub fn watch(mut self, events: Receiver<Event>) -> Receiver<AlertState> {
+        let (sender, receiver) = channel::<AlertState>();
+
+        thread::spawn(move || loop {
+            let change = match events.recv_timeout(WATCH_INTERVAL) {
+                Ok(event) => self.process(&event),
+                Err(RecvTimeoutError::Timeout) => self.update(),
+                Err(RecvTimeoutError::Disconnected) => return,
+            };
+
+            if let Some(state) = change {
+                if sender.send(state).is_err() {
+                    return;
+                }
+            }
+        });
+
+        receiver
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LightningEnergy;
+    use std::sync::{Arc, Mutex};
+
+    const MINUTE: Duration = Duration::from_secs(60);
+
+    #[derive(Clone)]
+    struct FakeClock(Arc<Mutex<Instant>>);
+
+    impl FakeClock {
+        fn new() -> Self {
+            FakeClock(Arc::new(Mutex::new(Instant::now())))
+        }
+
+        fn advance(&self, duration: Duration) {
+            *self.0.lock().unwrap() += duration;
+        }
+    }
+
+    impl Clock for FakeClock {
+        fn now(&self) -> Instant {
+            *self.0.lock().unwrap()
+        }
+    }
+
+    fn engine() -> (AlertEngine<FakeClock>, FakeClock) {
+        let clock = FakeClock::new();
+        let engine = AlertEngine::with_clock(AlertParameters::default(), clock.clone()).unwrap();
+
+        (engine, clock)
+    }
+
+    fn lightning(distance: HeadOfStormDistance) -> Event {
+        Event::Lightning(distance, LightningEnergy(1000))
+    }
+
+    fn level(change: Option<AlertState>) -> Option<AlertLevel> {
+        change.map(|state| state.level)
+    }
+
+    #[test]
+    fn escalates_with_the_distance_and_gives_the_all_clear() {
+        let (mut engine, clock) = engine();
+        assert_eq!(engine.state().level, AlertLevel::AllClear);
+
+        let far = lightning(HeadOfStormDistance::OutOfRange);
+        assert_eq!(level(engine.process(&far)), Some(AlertLevel::Watch));
+        assert_eq!(engine.state().all_clear_in, None);
+
+        let near = lightning(HeadOfStormDistance::Kilometers(17));
+        assert_eq!(level(engine.process(&near)), Some(AlertLevel::Warning));
+
+        let close = lightning(HeadOfStormDistance::Kilometers(10));
+        assert_eq!(level(engine.process(&close)), Some(AlertLevel::Danger));
+        assert_eq!(engine.state().all_clear_in, Some(30 * MINUTE));
+
+        clock.advance(29 * MINUTE);
+        assert_eq!(engine.update(), None);
+        assert_eq!(engine.state().all_clear_in, Some(MINUTE));
+
+        clock.advance(MINUTE);
+        let state = engine.update().unwrap();
+        assert_eq!(state.level, AlertLevel::AllClear);
+        assert_eq!(state.all_clear_in, None);
+    }
+
+    #[test]
+    fn overhead_lightning_is_dangerous() {
+        let parameters = AlertParameters::default().with_danger_distance(0);
+        let mut engine = AlertEngine::with_clock(parameters, FakeClock::new()).unwrap();
+
+        let overhead = lightning(HeadOfStormDistance::Overhead);
+        assert_eq!(level(engine.process(&overhead)), Some(AlertLevel::Danger));
+    }
+
+    #[test]
+    fn qualifying_strikes_restart_the_all_clear_countdown() {
+        let (mut engine, clock) = engine();
+
+        engine.process(&lightning(HeadOfStormDistance::Kilometers(20)));
+
+        clock.advance(20 * MINUTE);
+        assert_eq!(
+            engine.process(&lightning(HeadOfStormDistance::Kilometers(14))),
+            None
+        );
+        assert_eq!(engine.state().all_clear_in, Some(30 * MINUTE));
+
+        // beyond the warning distance, only the watch is kept up
+        clock.advance(20 * MINUTE);
+        engine.process(&lightning(HeadOfStormDistance::Kilometers(24)));
+        assert_eq!(engine.state().all_clear_in, Some(10 * MINUTE));
+
+        clock.advance(10 * MINUTE);
+        assert_eq!(level(engine.update()), Some(AlertLevel::Watch));
+
+        clock.advance(20 * MINUTE);
+        assert_eq!(level(engine.update()), Some(AlertLevel::AllClear));
+    }
+
+    #[test]
+    fn danger_decays_to_warning() {
+        let (mut engine, clock) = engine();
+
+        engine.process(&lightning(HeadOfStormDistance::Kilometers(5)));
+        clock.advance(20 * MINUTE);
+        engine.process(&lightning(HeadOfStormDistance::Kilometers(20)));
+        assert_eq!(engine.state().level, AlertLevel::Danger);
+
+        clock.advance(10 * MINUTE);
+        assert_eq!(level(engine.update()), Some(AlertLevel::Warning));
+        assert_eq!(engine.state().all_clear_in, Some(20 * MINUTE));
+
+        clock.advance(20 * MINUTE);
+        assert_eq!(level(engine.update()), Some(AlertLevel::AllClear));
+    }
+
+    #[test]
+    fn disturbers_and_noise_degrade_the_sensor_for_a_while() {
+        let (mut engine, clock) = engine();
+
+        let state = engine.process(&Event::Disturbance).unwrap();
+        assert!(state.degraded);
+        assert_eq!(state.level, AlertLevel::AllClear);
+
+        clock.advance(Duration::from_secs(30));
+        assert_eq!(engine.process(&Event::Noise), None);
+
+        clock.advance(Duration::from_secs(59));
+        assert_eq!(engine.update(), None);
+
+        clock.advance(Duration::from_secs(1));
+        assert!(!engine.update().unwrap().degraded);
+    }
+
+    #[test]
+    fn rejects_danger_distance_beyond_warning_distance() {
+        let parameters = AlertParameters::default()
+            .with_warning_distance(10)
+            .with_danger_distance(20);
+
+        assert!(AlertEngine::new(parameters).is_err());
+    }
+}
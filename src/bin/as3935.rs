@@ -0,0 +1,689 @@
+use as3935::config::SensorConfig;
+use as3935::diagnostics::{Oscillator, RegisterValue, ANTENNA_RESONANCE_FREQUENCY};
+use as3935::interface::i2c::DEFAULT_I2C_ADDRESS;
+use as3935::interface::Bus;
+use as3935::survey::Survey;
+use as3935::{Event, HeadOfStormDistance, ListeningParameters, Status, AS3935};
+use clap::{crate_version, App, AppSettings, Arg, ArgMatches, SubCommand};
+use serde::Serialize;
+use simple_signal::{set_handler, Signal};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::process::exit;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+type Result<T> = ::std::result::Result<T, Box<dyn Error>>;
+
+/// Command line flags of the listening parameters and the configuration file keys they map to.
+const PARAMETER_FLAGS: [(&str, &str, &str); 8] = [
+    (
+        "sensor-placing",
+        "sensor_placing",
+        "AFE gain preset: indoor or outdoor",
+    ),
+    ("afe-gain", "afe_gain", "AFE gain: 0-31"),
+    (
+        "minimum-lightning-threshold",
+        "minimum_lightning_threshold",
+        "Number of lightning needed to report an event: 1, 5, 9 or 16",
+    ),
+    (
+        "noise-floor-threshold",
+        "noise_floor_threshold",
+        "Noise floor level: 0-7",
+    ),
+    (
+        "signal-verification-threshold",
+        "signal_verification_threshold",
+        "Watchdog threshold: 0-10",
+    ),
+    (
+        "spike-rejection-threshold",
+        "spike_rejection_threshold",
+        "Spike rejection: 0-15",
+    ),
+    (
+        "ignore-disturbances",
+        "ignore_disturbances",
+        "Mask disturber events: true or false",
+    ),
+    (
+        "tuning-capacitors",
+        "tuning_capacitors",
+        "Antenna tuning capacitors in steps of 8 pF: 0-15",
+    ),
+];
+
+#[derive(Clone, Copy, Eq, PartialEq)]
+enum Output {
+    Human,
+    Json,
+}
+
+impl Output {
+    fn print<T: Serialize>(self, value: &T, human: impl FnOnce(&T) -> String) -> Result<()> {
+        match self {
+            Output::Human => println!("{}", human(value)),
+            Output::Json => println!("{}", serde_json::to_string(value)?),
+        }
+
+        Ok(())
+    }
+}
+
+fn main() {
+    let matches = app().get_matches();
+
+    if matches.is_present("verbose") {
+        simple_logger::init().unwrap();
+    }
+
+    if let Err(e) = run(&matches) {
+        eprintln!("error: {}", e);
+        exit(1);
+    }
+}
+
+fn app() -> App<'static, 'static> {
+    let mut app = App::new("as3935")
+        .version(crate_version!())
+        .about("Bring-up and diagnostics of the AS3935 Franklin Lightning Sensor")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .setting(AppSettings::VersionlessSubcommands)
+        .arg(
+            Arg::with_name("config")
+                .short("c")
+                .long("config")
+                .value_name("FILE")
+                .global(true)
+                .help("TOML/JSON configuration file, other flags take precedence over it"),
+        )
+        .arg(
+            Arg::with_name("i2c-bus")
+                .long("i2c-bus")
+                .value_name("BUS")
+                .global(true)
+                .conflicts_with("spi-bus")
+                .help("I²C bus the sensor is connected to [default: 1]"),
+        )
+        .arg(
+            Arg::with_name("address")
+                .long("address")
+                .value_name("ADDRESS")
+                .global(true)
+                .help(
+                    "I²C address of the sensor, decimal or 0x-prefixed hexadecimal [default: 0x03]",
+                ),
+        )
+        .arg(
+            Arg::with_name("spi-bus")
+                .long("spi-bus")
+                .value_name("BUS")
+                .global(true)
+                .requires("chip-select")
+                .help("SPI bus the sensor is connected to"),
+        )
+        .arg(
+            Arg::with_name("chip-select")
+                .long("chip-select")
+                .value_name("CS")
+                .global(true)
+                .help("SPI chip select of the sensor"),
+        )
+        .arg(
+            Arg::with_name("irq")
+                .long("irq")
+                .value_name("GPIO")
+                .global(true)
+                .help("BCM number of the GPIO pin connected to the IRQ line [default: 24]"),
+        )
+        .arg(
+            Arg::with_name("json")
+                .long("json")
+                .global(true)
+                .help("Prints the output as JSON, one document per line"),
+        )
+        .arg(
+            Arg::with_name("verbose")
+                .short("v")
+                .long("verbose")
+                .global(true)
+                .help("Logs the communication with the sensor"),
+        );
+
+    for (flag, _, help) in PARAMETER_FLAGS.iter() {
+        app = app.arg(
+            Arg::with_name(flag)
+                .long(flag)
+                .value_name("VALUE")
+                .global(true)
+                .help(help),
+        );
+    }
+
+    app.subcommand(
+        SubCommand::with_name("listen")
+            .about("Configures the sensor and prints the detected events")
+            .arg(
+                Arg::with_name("duration")
+                    .long("duration")
+                    .value_name("SECONDS")
+                    .help("Stops listening after the given time instead of waiting for Ctrl+C"),
+            )
+            .arg(
+                Arg::with_name("save-config")
+                    .long("save-config")
+                    .value_name("FILE")
+                    .help("Writes the running configuration to a TOML/JSON file"),
+            ),
+    )
+    .subcommand(SubCommand::with_name("dump").about("Prints the values of all registers"))
+    .subcommand(
+        SubCommand::with_name("selftest")
+            .about("Resets the sensor to defaults, checks the registers and the oscillators"),
+    )
+    .subcommand(
+        SubCommand::with_name("tune")
+            .about("Tunes the antenna to 500 kHz and configures the best tuning capacitors")
+            .arg(
+                Arg::with_name("window")
+                    .long("window")
+                    .value_name("MILLISECONDS")
+                    .default_value("100")
+                    .help("Measurement window for each tuning capacitors setting"),
+            ),
+    )
+    .subcommand(
+        SubCommand::with_name("measure")
+            .about("Measures the frequency of an oscillator displayed on the IRQ pin")
+            .arg(
+                Arg::with_name("oscillator")
+                    .required(true)
+                    .possible_values(&["lco", "srco", "trco"]),
+            )
+            .arg(
+                Arg::with_name("window")
+                    .long("window")
+                    .value_name("MILLISECONDS")
+                    .default_value("100")
+                    .help("Measurement window"),
+            ),
+    )
+    .subcommand(SubCommand::with_name("calibrate").about("Calibrates the RC oscillators"))
+    .subcommand(
+        SubCommand::with_name("survey")
+            .about("Surveys the site and recommends the listening parameters")
+            .arg(
+                Arg::with_name("duration")
+                    .long("duration")
+                    .value_name("MINUTES")
+                    .default_value("60")
+                    .help("Duration of the whole survey"),
+            )
+            .arg(
+                Arg::with_name("state")
+                    .long("state")
+                    .value_name("FILE")
+                    .help(
+                        "Saves the progress after every step, resumes from it with the saved duration if the file exists",
+                    ),
+            ),
+    )
+    .subcommand(
+        SubCommand::with_name("set")
+            .about("Writes a single listening parameter to the sensor")
+            .arg(
+                Arg::with_name("field")
+                    .required(true)
+                    .possible_values(&as3935::config::LISTENING_KEYS)
+                    .help("Parameter name as used in the configuration file"),
+            )
+            .arg(Arg::with_name("value").required(true)),
+    )
+    .subcommand(
+        SubCommand::with_name("watch")
+            .about("Periodically reads the registers and prints the ones that changed")
+            .arg(
+                Arg::with_name("interval")
+                    .long("interval")
+                    .value_name("MILLISECONDS")
+                    .default_value("1000"),
+            ),
+    )
+}
+
+fn run(matches: &ArgMatches) -> Result<()> {
+    let (subcommand, subcommand_matches) = matches.subcommand();
+    let subcommand_matches = subcommand_matches.unwrap();
+
+    let output = if subcommand_matches.is_present("json") {
+        Output::Json
+    } else {
+        Output::Human
+    };
+
+    let config = sensor_config(subcommand_matches)?;
+    let mut sensor = config.connect()?;
+
+    match subcommand {
+        "listen" => listen(&mut sensor, &config, subcommand_matches, output),
+        "dump" => dump(&mut sensor, output),
+        "selftest" => selftest(&mut sensor, output),
+        "tune" => tune(&mut sensor, subcommand_matches, output),
+        "measure" => measure(&mut sensor, subcommand_matches, output),
+        "calibrate" => calibrate(&mut sensor, output),
+        "survey" => survey(&mut sensor, subcommand_matches, output),
+        "set" => set(&mut sensor, subcommand_matches, output),
+        "watch" => watch(&mut sensor, subcommand_matches, output),
+        _ => unreachable!(),
+    }
+}
+
+fn sensor_config(matches: &ArgMatches) -> Result<SensorConfig> {
+    let mut config = match matches.value_of("config") {
+        Some(path) => SensorConfig::from_file(path)?,
+        None => SensorConfig {
+            bus: Bus::I2c {
+                bus: 1,
+                address: DEFAULT_I2C_ADDRESS,
+            },
+            irq_pin: 24,
+            parameters: ListeningParameters::default(),
+        },
+    };
+
+    if let Some(spi_bus) = matches.value_of("spi-bus") {
+        config.bus = Bus::Spi {
+            bus: parse_number("spi-bus", spi_bus)?,
+            chip_select: parse_number("chip-select", matches.value_of("chip-select").unwrap())?,
+        };
+    } else if matches.is_present("i2c-bus") || matches.is_present("address") {
+        let (mut bus, mut address) = match config.bus {
+            Bus::I2c { bus, address } => (bus, address),
+            Bus::Spi { .. } => (1, DEFAULT_I2C_ADDRESS),
+        };
+
+        if let Some(value) = matches.value_of("i2c-bus") {
+            bus = parse_number("i2c-bus", value)?;
+        }
+
+        if let Some(value) = matches.value_of("address") {
+            address = parse_number("address", value)?;
+        }
+
+        config.bus = Bus::I2c { bus, address };
+    }
+
+    if let Some(irq) = matches.value_of("irq") {
+        config.irq_pin = parse_number("irq", irq)?;
+    }
+
+    for (flag, key, _) in PARAMETER_FLAGS.iter() {
+        if let Some(value) = matches.value_of(flag) {
+            config.parameters = config.parameters.with_field(key, value)?;
+        }
+    }
+
+    Ok(config)
+}
+
+fn parse_number(flag: &str, value: &str) -> Result<u8> {
+    let parsed = if let Some(hexadecimal) = value.strip_prefix("0x") {
+        u8::from_str_radix(hexadecimal, 16)
+    } else {
+        value.parse()
+    };
+
+    parsed.map_err(|_| format!("invalid value of --{}: {}", flag, value).into())
+}
+
+fn parse_duration(flag: &str, value: &str, unit: Duration) -> Result<Duration> {
+    match value.parse::<u32>() {
+        Ok(n) if n > 0 => Ok(unit * n),
+        _ => Err(format!(
+            "invalid value of --{}: {}, a positive number is expected",
+            flag, value
+        )
+        .into()),
+    }
+}
+
+/// Channel receiving a message once the process is asked to terminate.
+fn termination_signal() -> Receiver<()> {
+    let (sender, receiver) = channel::<()>();
+
+    set_handler(&[Signal::Int, Signal::Term], move |_signals| {
+        let _ = sender.send(());
+    });
+
+    receiver
+}
+
+fn listen(
+    sensor: &mut AS3935,
+    config: &SensorConfig,
+    matches: &ArgMatches,
+    output: Output,
+) -> Result<()> {
+    let duration = match matches.value_of("duration") {
+        Some(value) => Some(parse_duration("duration", value, Duration::from_secs(1))?),
+        None => None,
+    };
+
+    let termination = termination_signal();
+    let events = sensor.listen(config.parameters)?;
+
+    if let Some(path) = matches.value_of("save-config") {
+        sensor.to_config().write_to_file(path)?;
+    }
+
+    if output == Output::Human {
+        println!("Listening for events…");
+    }
+
+    thread::spawn(move || {
+        for event in events {
+            let timestamp = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs();
+
+            let _ = output.print(&event, |event| {
+                format!("[{}] {}", timestamp, describe_event(event))
+            });
+        }
+    });
+
+    match duration {
+        Some(duration) => {
+            let _ = termination.recv_timeout(duration);
+        }
+        None => {
+            let _ = termination.recv();
+        }
+    }
+
+    sensor.terminate()?;
+
+    Ok(())
+}
+
+fn describe_event(event: &Event) -> String {
+    match event {
+        Event::Lightning(distance, energy) => format!(
+            "Lightning detected: {} (energy {}).",
+            match distance {
+                HeadOfStormDistance::Kilometers(km) => format!("{} km", km),
+                HeadOfStormDistance::OutOfRange => String::from("out of range"),
+                HeadOfStormDistance::Overhead => String::from("overhead"),
+            },
+            energy.value()
+        ),
+        Event::Noise => String::from("Noise detected."),
+        Event::Disturbance => String::from("Disturber detected."),
+        Event::Status(Status::SensorReset) => {
+            String::from("Sensor was reset, its configuration was re-applied.")
+        }
+        Event::Status(Status::SensorUnavailable) => {
+            String::from("Sensor does not respond on the bus.")
+        }
+        Event::Status(Status::Recalibrated(calibration)) => format!(
+            "RC oscillators recalibrated: TRCO {}, SRCO {}.",
+            if calibration.trco { "ok" } else { "failed" },
+            if calibration.srco { "ok" } else { "failed" },
+        ),
+    }
+}
+
+fn describe_register(register: &RegisterValue) -> String {
+    format!(
+        "{:#04x} {:<16} {:>#10b}  {}",
+        register.address, register.name, register.value, register.description
+    )
+}
+
+fn dump(sensor: &mut AS3935, output: Output) -> Result<()> {
+    let registers = sensor.dump()?;
+
+    output.print(&registers, |registers| {
+        registers
+            .iter()
+            .map(describe_register)
+            .collect::<Vec<_>>()
+            .join("\n")
+    })
+}
+
+fn selftest(sensor: &mut AS3935, output: Output) -> Result<()> {
+    let self_test = sensor.self_test()?;
+
+    output.print(&self_test, |self_test| {
+        self_test
+            .checks
+            .iter()
+            .map(|check| {
+                format!(
+                    "[{}] {}: {}",
+                    if check.passed { "PASS" } else { "FAIL" },
+                    check.name,
+                    check.detail
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    })?;
+
+    if !self_test.passed() {
+        exit(2);
+    }
+
+    Ok(())
+}
+
+fn tune(sensor: &mut AS3935, matches: &ArgMatches, output: Output) -> Result<()> {
+    let window = parse_duration(
+        "window",
+        matches.value_of("window").unwrap(),
+        Duration::from_millis(1),
+    )?;
+
+    let tuning = sensor.tune_antenna(window)?;
+
+    output.print(&tuning, |tuning| {
+        let mut lines: Vec<String> = tuning
+            .measurements
+            .iter()
+            .map(|measurement| {
+                format!(
+                    "{:>3} pF: {:>9.0} Hz",
+                    measurement.tuning_capacitors.picofarads(),
+                    measurement.frequency
+                )
+            })
+            .collect();
+
+        lines.push(format!(
+            "Configured {} pF, {:.0} Hz ({:+.1} % from {:.0} Hz){}",
+            tuning.best.tuning_capacitors.picofarads(),
+            tuning.best.frequency,
+            tuning.deviation() * 100.0,
+            ANTENNA_RESONANCE_FREQUENCY,
+            if tuning.is_within_tolerance() {
+                ""
+            } else {
+                ", outside of the ±3.5 % tolerance"
+            }
+        ));
+
+        lines.join("\n")
+    })?;
+
+    if !tuning.is_within_tolerance() {
+        exit(2);
+    }
+
+    Ok(())
+}
+
+fn measure(sensor: &mut AS3935, matches: &ArgMatches, output: Output) -> Result<()> {
+    let oscillator = match matches.value_of("oscillator").unwrap() {
+        "lco" => Oscillator::Lco,
+        "srco" => Oscillator::Srco,
+        _ => Oscillator::Trco,
+    };
+    let window = parse_duration(
+        "window",
+        matches.value_of("window").unwrap(),
+        Duration::from_millis(1),
+    )?;
+
+    let measurement = sensor.measure_oscillator(oscillator, window)?;
+
+    output.print(&measurement, |measurement| {
+        format!(
+            "{:?}: {:.0} Hz ({:+.1} % from {:.0} Hz)",
+            measurement.oscillator,
+            measurement.frequency,
+            measurement.deviation() * 100.0,
+            measurement.nominal
+        )
+    })
+}
+
+fn survey(sensor: &mut AS3935, matches: &ArgMatches, output: Output) -> Result<()> {
+    let duration = parse_duration(
+        "duration",
+        matches.value_of("duration").unwrap(),
+        Duration::from_secs(60),
+    )?;
+    let state = matches.value_of("state").map(Path::new);
+
+    let mut survey: Survey = match state {
+        Some(path) if path.exists() => serde_json::from_str(&fs::read_to_string(path)?)?,
+        _ => Survey::new(duration)?,
+    };
+
+    // the saved survey keeps its duration, resuming it with another one would mix up the rates
+    if matches.occurrences_of("duration") > 0 && Survey::new(duration)?.dwell() != survey.dwell() {
+        return Err(format!(
+            "the saved survey lasts {:.0} min, remove --duration or the state file to change it",
+            (survey.dwell() * survey.total() as u32).as_secs_f64() / 60.0
+        )
+        .into());
+    }
+
+    let mut saving = Ok(());
+    let report = sensor.resume_survey(&mut survey, |survey| {
+        eprintln!(
+            "Step {} of {} done, {} min remaining",
+            survey.completed(),
+            survey.total(),
+            survey.remaining().as_secs() / 60
+        );
+
+        if let (Some(path), Ok(())) = (state, &saving) {
+            saving = serde_json::to_string(survey)
+                .map_err(Box::<dyn Error>::from)
+                .and_then(|json| fs::write(path, json).map_err(Box::<dyn Error>::from));
+        }
+    })?;
+    saving?;
+
+    output.print(&report, |report| {
+        let mut lines: Vec<String> = report
+            .evidence
+            .iter()
+            .map(|step| {
+                format!(
+                    "{:?}: {:.2} noise/min, {:.2} disturbers/min",
+                    step.parameters,
+                    step.noise_rate(),
+                    step.disturber_rate()
+                )
+            })
+            .collect();
+
+        lines.push(format!(
+            "Recommended {:?}{}",
+            report.recommendation,
+            if report.acceptable {
+                ""
+            } else {
+                ", the rates are above the acceptable ones even so"
+            }
+        ));
+
+        lines.join("\n")
+    })
+}
+
+fn calibrate(sensor: &mut AS3935, output: Output) -> Result<()> {
+    let status = sensor.calibrate()?;
+
+    output.print(&status, |status| {
+        format!(
+            "TRCO {}, SRCO {}",
+            if status.trco { "calibrated" } else { "failed" },
+            if status.srco { "calibrated" } else { "failed" },
+        )
+    })?;
+
+    if !status.is_successful() {
+        exit(2);
+    }
+
+    Ok(())
+}
+
+fn set(sensor: &mut AS3935, matches: &ArgMatches, output: Output) -> Result<()> {
+    let (field, value) = (
+        matches.value_of("field").unwrap(),
+        matches.value_of("value").unwrap(),
+    );
+
+    let parameters = ListeningParameters::default().with_field(field, value)?;
+    sensor.configure(parameters)?;
+
+    output.print(&parameters, |_| format!("{} = {}", field, value))
+}
+
+fn watch(sensor: &mut AS3935, matches: &ArgMatches, output: Output) -> Result<()> {
+    let interval = parse_duration(
+        "interval",
+        matches.value_of("interval").unwrap(),
+        Duration::from_millis(1),
+    )?;
+
+    let termination = termination_signal();
+    let mut previous: Vec<RegisterValue> = Vec::new();
+
+    loop {
+        let registers = sensor.dump()?;
+
+        let changed: Vec<RegisterValue> = registers
+            .iter()
+            .filter(|register| !previous.contains(register))
+            .cloned()
+            .collect();
+
+        if !changed.is_empty() {
+            output.print(&changed, |changed| {
+                changed
+                    .iter()
+                    .map(describe_register)
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            })?;
+        }
+
+        previous = registers;
+
+        match termination.recv_timeout(interval) {
+            Err(RecvTimeoutError::Timeout) => continue,
+            Ok(_) | Err(RecvTimeoutError::Disconnected) => return Ok(()),
+        }
+    }
+}
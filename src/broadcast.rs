@@ -0,0 +1,110 @@
+use crate::queue::{self, EventSender, QueueStats};
+use crate::{Error, Event, HeadOfStormDistance, Result, AS3935};
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, Mutex};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum EventKind {
+    Disturbance,
+    Lightning,
+    Noise,
+    Status,
+}
+
+impl From<&Event> for EventKind {
+    fn from(event: &Event) -> Self {
+        match event {
+            Event::Disturbance => EventKind::Disturbance,
+            Event::Lightning(..) => EventKind::Lightning,
+            Event::Noise => EventKind::Noise,
+            Event::Status(_) => EventKind::Status,
+        }
+    }
+}
+
+/// Selects the events delivered to a subscription, every event passes the default filter.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EventFilter {
+    kinds: Option<Vec<EventKind>>,
+    max_distance: Option<u8>,
+}
+
+impl EventFilter {
+    pub fn with_kinds(mut self, kinds: &[EventKind]) -> Self {
+        self.kinds = Some(kinds.to_vec());
+        self
+    }
+
+    /// Lets through only lightning within `kilometers`, including overhead ones; other kinds are not affected.
+    pub fn with_max_distance(mut self, kilometers: u8) -> Self {
+        self.max_distance = Some(kilometers);
+        self
+    }
+
+    pub fn matches(&self, event: &Event) -> bool {
+        if let Some(kinds) = &self.kinds {
+            if !kinds.contains(&EventKind::from(event)) {
+                return false;
+            }
+        }
+
+        match (event, self.max_distance) {
+            (Event::Lightning(distance, _), Some(max_distance)) => match distance {
+                HeadOfStormDistance::Overhead => true,
+                HeadOfStormDistance::Kilometers(km) => *km <= max_distance,
+                HeadOfStormDistance::OutOfRange => false,
+            },
+            _ => true,
+        }
+    }
+}
+
+/// Subscriptions of a listening sensor, dropping the last reference closes all of them.
+#[derive(Default)]
+pub(crate) struct Subscribers(Mutex<Vec<(EventFilter, EventSender)>>);
+
+impl Subscribers {
+    pub(crate) fn add(&self, filter: EventFilter, sender: EventSender) {
+        self.0.lock().unwrap().push((filter, sender));
+    }
+
+    /// Delivers the event to the matching subscriptions, forgetting those whose receiver was dropped.
+    pub(crate) fn publish(&self, event: Event) {
+        self.0.lock().unwrap().retain(|(filter, sender)| {
+            if !filter.matches(&event) || sender.send(event) {
+                return true;
+            }
+
+            debug!("subscription receiver was dropped, unsubscribing");
+            false
+        });
+    }
+}
+
+impl AS3935 {
+    /// Subscribes to the events of the listening sensor which pass `filter`.
+    ///
+    /// Each subscription gets its own channel, bounded like the one returned by [`listen`](AS3935::listen), and
+    /// ends when it's dropped or when the listening is terminated.
+    pub fn subscribe(&self, filter: EventFilter) -> Result<Receiver<Event>> {
+        self.subscribe_with_stats(filter)
+            .map(|(receiver, _)| receiver)
+    }
+
+    /// Like [`subscribe`](AS3935::subscribe), also returns the counters of the events the subscription's own
+    /// bounded queue dropped.
+    pub fn subscribe_with_stats(
+        &self,
+        filter: EventFilter,
+    ) -> Result<(Receiver<Event>, Arc<QueueStats>)> {
+        let subscribers = self.subscribers.upgrade().ok_or(Error::InvalidState)?;
+
+        let stats = Arc::new(QueueStats::default());
+        let (sender, receiver) = queue::channel(self.event_queue, stats.clone());
+        subscribers.add(filter, sender);
+
+        Ok((receiver, stats))
+    }
+}
@@ -0,0 +1,641 @@
+use crate::interface::i2c::{I2cAddress, DEFAULT_I2C_ADDRESS};
+use crate::interface::Bus;
+use crate::{
+    AfeGain, IgnoreDisturbances, InterfaceSelection, ListeningParameters,
+    MinimumLightningThreshold, NoiseFloorThreshold, SensorPlacing, SignalVerificationThreshold,
+    SpikeRejectionThreshold, TuningCapacitors, AS3935,
+};
+use rppal::gpio::Gpio;
+use rppal::i2c::I2c;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+pub type Result<T> = ::std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    /// The file is not valid TOML/JSON or does not match the expected structure.
+    Parse(String),
+    /// A value is syntactically fine, but not acceptable for the sensor.
+    Invalid {
+        key: &'static str,
+        reason: &'static str,
+    },
+    UnknownKey(String),
+    Gpio(rppal::gpio::Error),
+    Device(crate::Error),
+}
+
+impl ::std::error::Error for Error {}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> ::std::result::Result<(), fmt::Error> {
+        match self {
+            Error::Io(e) => e.fmt(f),
+            Error::Parse(message) => write!(f, "invalid configuration: {}", message),
+            Error::Invalid { key, reason } => write!(f, "invalid value of `{}`: {}", key, reason),
+            Error::UnknownKey(key) => write!(f, "unknown key `{}`", key),
+            Error::Gpio(e) => e.fmt(f),
+            Error::Device(e) => e.fmt(f),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+impl From<rppal::gpio::Error> for Error {
+    fn from(error: rppal::gpio::Error) -> Self {
+        Error::Gpio(error)
+    }
+}
+
+impl From<crate::Error> for Error {
+    fn from(error: crate::Error) -> Self {
+        Error::Device(error)
+    }
+}
+
+impl From<crate::interface::Error> for Error {
+    fn from(error: crate::interface::Error) -> Self {
+        Error::Device(error.into())
+    }
+}
+
+/// Format of a configuration file.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Format {
+    Toml,
+    Json,
+}
+
+impl Format {
+    /// Guesses the format from the file extension, defaulting to TOML.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Self {
+        match path
+            .as_ref()
+            .extension()
+            .and_then(|extension| extension.to_str())
+        {
+            Some("json") => Format::Json,
+            _ => Format::Toml,
+        }
+    }
+}
+
+/// Sensor configuration as stored in a configuration file.
+///
+/// The TOML form of the file looks like this, the JSON form has the same structure:
+///
+/// ```toml
+/// # BCM number of the GPIO pin the IRQ line is connected to
+/// irq_pin = 24
+///
+/// [bus]
+/// # "i2c" or "spi"
+/// type = "i2c"
+/// bus = 1
+/// # 0-127, defaults to 3
+/// address = 3
+/// # for SPI, `bus` and `chip_select` are used instead
+///
+/// # every key of this table is optional, the sensor default is used when omitted
+/// [listening]
+/// # "indoor" or "outdoor", the preset of the AFE gain
+/// sensor_placing = "outdoor"
+/// # 0-31, instead of sensor_placing
+/// # afe_gain = 14
+/// # 1, 5, 9 or 16
+/// minimum_lightning_threshold = 1
+/// # 0-7
+/// noise_floor_threshold = 2
+/// # 0-10
+/// signal_verification_threshold = 2
+/// # 0-15
+/// spike_rejection_threshold = 2
+/// ignore_disturbances = false
+/// # 0-15, in steps of 8 pF
+/// tuning_capacitors = 0
+/// ```
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SensorConfig {
+    pub bus: Bus,
+    pub irq_pin: u8,
+    pub parameters: ListeningParameters,
+}
+
+impl SensorConfig {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let contents = fs::read_to_string(&path)?;
+
+        Self::parse(&contents, Format::from_path(path))
+    }
+
+    pub fn parse(contents: &str, format: Format) -> Result<Self> {
+        let raw: RawConfig = match format {
+            Format::Toml => toml::from_str(contents).map_err(|e| Error::Parse(e.to_string()))?,
+            Format::Json => {
+                serde_json::from_str(contents).map_err(|e| Error::Parse(e.to_string()))?
+            }
+        };
+
+        raw.validate()
+    }
+
+    pub fn write_to_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let contents = self.serialize(Format::from_path(&path))?;
+
+        fs::write(path, contents)?;
+
+        Ok(())
+    }
+
+    pub fn serialize(&self, format: Format) -> Result<String> {
+        let raw = RawConfig::from(self);
+
+        match format {
+            Format::Toml => toml::to_string(&raw).map_err(|e| Error::Parse(e.to_string())),
+            Format::Json => {
+                serde_json::to_string_pretty(&raw).map_err(|e| Error::Parse(e.to_string()))
+            }
+        }
+    }
+
+    /// Opens the configured bus and IRQ pin. The sensor is left standing by, pass
+    /// [`parameters`](SensorConfig::parameters) to [`AS3935::listen`] to start listening.
+    pub fn connect(&self) -> Result<AS3935> {
+        let irq_pin = Gpio::new()?.get(self.irq_pin)?.into_input();
+
+        let interface_selection = match self.bus {
+            Bus::I2c { address, .. } if address > 127 => {
+                return Err(Error::Invalid {
+                    key: "bus.address",
+                    reason: "I2C address must be in range 0-127",
+                })
+            }
+            Bus::I2c { bus, address } => InterfaceSelection::I2c(
+                I2c::with_bus(bus).map_err(crate::interface::Error::from)?,
+                I2cAddress::new(address),
+            ),
+            Bus::Spi { .. } => {
+                return Err(Error::Invalid {
+                    key: "bus.type",
+                    reason: "SPI interface is not supported yet",
+                })
+            }
+        };
+
+        Ok(AS3935::new(interface_selection, irq_pin)?)
+    }
+}
+
+impl AS3935 {
+    /// Configuration of the running sensor, suitable for writing back to a configuration file.
+    ///
+    /// Sensors which are not driven by a GPIO pin, such as replayed ones, report pin 0.
+    pub fn to_config(&self) -> SensorConfig {
+        SensorConfig {
+            bus: self.bus(),
+            irq_pin: self.irq_pin.pin().unwrap_or_default(),
+            parameters: self.parameters(),
+        }
+    }
+}
+
+/// Keys of the `listening` table, accepted by [`ListeningParameters::with_field`].
+pub const LISTENING_KEYS: [&str; 8] = [
+    "sensor_placing",
+    "afe_gain",
+    "minimum_lightning_threshold",
+    "noise_floor_threshold",
+    "signal_verification_threshold",
+    "spike_rejection_threshold",
+    "ignore_disturbances",
+    "tuning_capacitors",
+];
+
+impl ListeningParameters {
+    /// Sets a parameter identified by its key in the `listening` table of the configuration file,
+    /// parsing the value the same way the configuration file is parsed.
+    ///
+    /// The value is validated together with the parameters already set, so setting the AFE gain on
+    /// parameters with a sensor placing (or the other way round) fails just like in a configuration file.
+    pub fn with_field(self, key: &str, value: &str) -> Result<Self> {
+        fn number(key: &'static str, value: &str) -> Result<u8> {
+            value.parse().map_err(|_| Error::Invalid {
+                key,
+                reason: "Value must be a non-negative number",
+            })
+        }
+
+        let mut raw = RawListening::from(&self);
+
+        match key {
+            "sensor_placing" => raw.sensor_placing = Some(value.to_owned()),
+            "afe_gain" => raw.afe_gain = Some(number("listening.afe_gain", value)?),
+            "minimum_lightning_threshold" => {
+                raw.minimum_lightning_threshold =
+                    Some(number("listening.minimum_lightning_threshold", value)?)
+            }
+            "noise_floor_threshold" => {
+                raw.noise_floor_threshold = Some(number("listening.noise_floor_threshold", value)?)
+            }
+            "signal_verification_threshold" => {
+                raw.signal_verification_threshold =
+                    Some(number("listening.signal_verification_threshold", value)?)
+            }
+            "spike_rejection_threshold" => {
+                raw.spike_rejection_threshold =
+                    Some(number("listening.spike_rejection_threshold", value)?)
+            }
+            "ignore_disturbances" => {
+                raw.ignore_disturbances = Some(value.parse().map_err(|_| Error::Invalid {
+                    key: "listening.ignore_disturbances",
+                    reason: "Value must be either true or false",
+                })?)
+            }
+            "tuning_capacitors" => {
+                raw.tuning_capacitors = Some(number("listening.tuning_capacitors", value)?)
+            }
+            _ => return Err(Error::UnknownKey(key.to_owned())),
+        }
+
+        raw.validate()
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct RawConfig {
+    irq_pin: u8,
+    bus: RawBus,
+    #[serde(default)]
+    listening: RawListening,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+enum RawBus {
+    I2c {
+        bus: u8,
+        #[serde(default = "default_i2c_address")]
+        address: u8,
+    },
+    Spi {
+        bus: u8,
+        chip_select: u8,
+    },
+}
+
+fn default_i2c_address() -> u8 {
+    DEFAULT_I2C_ADDRESS
+}
+
+#[derive(Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct RawListening {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sensor_placing: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    afe_gain: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    minimum_lightning_threshold: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    noise_floor_threshold: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    signal_verification_threshold: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    spike_rejection_threshold: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ignore_disturbances: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tuning_capacitors: Option<u8>,
+}
+
+impl RawConfig {
+    fn validate(self) -> Result<SensorConfig> {
+        let bus = match self.bus {
+            RawBus::I2c { address, .. } if address > 127 => {
+                return Err(Error::Invalid {
+                    key: "bus.address",
+                    reason: "I2C address must be in range 0-127",
+                })
+            }
+            RawBus::I2c { bus, address } => Bus::I2c { bus, address },
+            RawBus::Spi { bus, chip_select } => Bus::Spi { bus, chip_select },
+        };
+
+        Ok(SensorConfig {
+            bus,
+            irq_pin: self.irq_pin,
+            parameters: self.listening.validate()?,
+        })
+    }
+}
+
+impl RawListening {
+    fn validate(self) -> Result<ListeningParameters> {
+        let invalid = |key, reason| Error::Invalid { key, reason };
+        let mut parameters = ListeningParameters::default();
+
+        if let Some(sensor_placing) = self.sensor_placing {
+            parameters = parameters.with_sensor_placing(match sensor_placing.as_str() {
+                "indoor" => SensorPlacing::Indoor,
+                "outdoor" => SensorPlacing::Outdoor,
+                _ => {
+                    return Err(invalid(
+                        "listening.sensor_placing",
+                        "Sensor placing must be either \"indoor\" or \"outdoor\"",
+                    ))
+                }
+            });
+        }
+
+        if let Some(afe_gain) = self.afe_gain {
+            if parameters.afe_gain.is_some() {
+                return Err(invalid(
+                    "listening.afe_gain",
+                    "AFE gain can't be set together with the sensor placing",
+                ));
+            }
+
+            parameters = parameters.with_afe_gain(
+                AfeGain::new(afe_gain).map_err(|reason| invalid("listening.afe_gain", reason))?,
+            );
+        }
+
+        if let Some(minimum_lightning_threshold) = self.minimum_lightning_threshold {
+            parameters =
+                parameters.with_minimum_lightning_threshold(match minimum_lightning_threshold {
+                    1 => MinimumLightningThreshold::One,
+                    5 => MinimumLightningThreshold::Five,
+                    9 => MinimumLightningThreshold::Nine,
+                    16 => MinimumLightningThreshold::Sixteen,
+                    _ => {
+                        return Err(invalid(
+                            "listening.minimum_lightning_threshold",
+                            "Minimum lightning threshold must be one of 1, 5, 9 or 16",
+                        ))
+                    }
+                });
+        }
+
+        if let Some(noise_floor_threshold) = self.noise_floor_threshold {
+            parameters = parameters.with_noise_floor_threshold(
+                NoiseFloorThreshold::new(noise_floor_threshold)
+                    .map_err(|reason| invalid("listening.noise_floor_threshold", reason))?,
+            );
+        }
+
+        if let Some(signal_verification_threshold) = self.signal_verification_threshold {
+            parameters = parameters.with_signal_verification_threshold(
+                SignalVerificationThreshold::new(signal_verification_threshold)
+                    .map_err(|reason| invalid("listening.signal_verification_threshold", reason))?,
+            );
+        }
+
+        if let Some(spike_rejection_threshold) = self.spike_rejection_threshold {
+            parameters = parameters.with_spike_rejection_threshold(
+                SpikeRejectionThreshold::new(spike_rejection_threshold)
+                    .map_err(|reason| invalid("listening.spike_rejection_threshold", reason))?,
+            );
+        }
+
+        if let Some(ignore_disturbances) = self.ignore_disturbances {
+            parameters = parameters.with_ignore_disturbances(if ignore_disturbances {
+                IgnoreDisturbances::Yes
+            } else {
+                IgnoreDisturbances::No
+            });
+        }
+
+        if let Some(tuning_capacitors) = self.tuning_capacitors {
+            parameters = parameters.with_tuning_capacitors(
+                TuningCapacitors::new(tuning_capacitors)
+                    .map_err(|reason| invalid("listening.tuning_capacitors", reason))?,
+            );
+        }
+
+        Ok(parameters)
+    }
+}
+
+impl From<&SensorConfig> for RawConfig {
+    fn from(config: &SensorConfig) -> Self {
+        Self {
+            irq_pin: config.irq_pin,
+            bus: match config.bus {
+                Bus::I2c { bus, address } => RawBus::I2c { bus, address },
+                Bus::Spi { bus, chip_select } => RawBus::Spi { bus, chip_select },
+            },
+            listening: RawListening::from(&config.parameters),
+        }
+    }
+}
+
+impl From<&ListeningParameters> for RawListening {
+    fn from(parameters: &ListeningParameters) -> Self {
+        Self {
+            sensor_placing: parameters
+                .afe_gain
+                .and_then(AfeGain::placing)
+                .map(|sensor_placing| {
+                    match sensor_placing {
+                        SensorPlacing::Indoor => "indoor",
+                        SensorPlacing::Outdoor => "outdoor",
+                    }
+                    .to_owned()
+                }),
+            afe_gain: parameters
+                .afe_gain
+                .filter(|afe_gain| afe_gain.placing().is_none())
+                .map(|n| n.0),
+            minimum_lightning_threshold: parameters.minimum_lightning_threshold.map(
+                |minimum_lightning_threshold| match minimum_lightning_threshold {
+                    MinimumLightningThreshold::One => 1,
+                    MinimumLightningThreshold::Five => 5,
+                    MinimumLightningThreshold::Nine => 9,
+                    MinimumLightningThreshold::Sixteen => 16,
+                },
+            ),
+            noise_floor_threshold: parameters.noise_floor_threshold.map(|n| n.0),
+            signal_verification_threshold: parameters.signal_verification_threshold.map(|n| n.0),
+            spike_rejection_threshold: parameters.spike_rejection_threshold.map(|n| n.0),
+            ignore_disturbances: parameters
+                .ignore_disturbances
+                .map(|ignore_disturbances| ignore_disturbances == IgnoreDisturbances::Yes),
+            tuning_capacitors: parameters.tuning_capacitors.map(|n| n.0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = r#"
+irq_pin = 24
+
+[bus]
+type = "i2c"
+bus = 1
+
+[listening]
+sensor_placing = "outdoor"
+minimum_lightning_threshold = 5
+noise_floor_threshold = 2
+ignore_disturbances = true
+"#;
+
+    fn invalid_key(result: Result<impl fmt::Debug>) -> &'static str {
+        match result {
+            Err(Error::Invalid { key, .. }) => key,
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    fn parse_error(result: Result<SensorConfig>) {
+        match result {
+            Err(Error::Parse(_)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    fn with_listening(listening: &str) -> Result<SensorConfig> {
+        SensorConfig::parse(
+            &format!(
+                "irq_pin = 24\n[bus]\ntype = \"i2c\"\nbus = 1\n[listening]\n{}\n",
+                listening
+            ),
+            Format::Toml,
+        )
+    }
+
+    #[test]
+    fn parses_a_configuration_file() {
+        let config = SensorConfig::parse(EXAMPLE, Format::Toml).unwrap();
+
+        assert_eq!(
+            config,
+            SensorConfig {
+                bus: Bus::I2c {
+                    bus: 1,
+                    address: DEFAULT_I2C_ADDRESS,
+                },
+                irq_pin: 24,
+                parameters: ListeningParameters::default()
+                    .with_sensor_placing(SensorPlacing::Outdoor)
+                    .with_minimum_lightning_threshold(MinimumLightningThreshold::Five)
+                    .with_noise_floor_threshold(NoiseFloorThreshold::new(2).unwrap())
+                    .with_ignore_disturbances(IgnoreDisturbances::Yes),
+            }
+        );
+    }
+
+    #[test]
+    fn serialized_configuration_parses_back() {
+        let config = SensorConfig::parse(EXAMPLE, Format::Toml).unwrap();
+
+        for &format in &[Format::Toml, Format::Json] {
+            let contents = config.serialize(format).unwrap();
+            assert_eq!(SensorConfig::parse(&contents, format).unwrap(), config);
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        parse_error(SensorConfig::parse(
+            "irq_pin = 24\nirq = 25\n[bus]\ntype = \"i2c\"\nbus = 1\n",
+            Format::Toml,
+        ));
+        parse_error(SensorConfig::parse(
+            "irq_pin = 24\n[bus]\ntype = \"i2c\"\nbus = 1\nadress = 3\n",
+            Format::Toml,
+        ));
+        parse_error(with_listening("afe = 14"));
+    }
+
+    #[test]
+    fn rejects_values_the_sensor_does_not_accept() {
+        assert_eq!(
+            invalid_key(SensorConfig::parse(
+                "irq_pin = 24\n[bus]\ntype = \"i2c\"\nbus = 1\naddress = 128\n",
+                Format::Toml,
+            )),
+            "bus.address"
+        );
+        assert_eq!(
+            invalid_key(with_listening("sensor_placing = \"attic\"")),
+            "listening.sensor_placing"
+        );
+        assert_eq!(
+            invalid_key(with_listening("afe_gain = 32")),
+            "listening.afe_gain"
+        );
+        assert_eq!(
+            invalid_key(with_listening("minimum_lightning_threshold = 2")),
+            "listening.minimum_lightning_threshold"
+        );
+        assert_eq!(
+            invalid_key(with_listening("noise_floor_threshold = 8")),
+            "listening.noise_floor_threshold"
+        );
+        assert_eq!(
+            invalid_key(with_listening("signal_verification_threshold = 11")),
+            "listening.signal_verification_threshold"
+        );
+        assert_eq!(
+            invalid_key(with_listening("spike_rejection_threshold = 16")),
+            "listening.spike_rejection_threshold"
+        );
+        assert_eq!(
+            invalid_key(with_listening("tuning_capacitors = 16")),
+            "listening.tuning_capacitors"
+        );
+    }
+
+    #[test]
+    fn rejects_afe_gain_together_with_sensor_placing() {
+        assert_eq!(
+            invalid_key(with_listening("sensor_placing = \"indoor\"\nafe_gain = 14")),
+            "listening.afe_gain"
+        );
+    }
+
+    #[test]
+    fn fields_are_validated_together_with_the_parameters_already_set() {
+        let parameters = ListeningParameters::default()
+            .with_field("sensor_placing", "indoor")
+            .unwrap();
+
+        assert_eq!(
+            invalid_key(parameters.with_field("afe_gain", "7")),
+            "listening.afe_gain"
+        );
+        assert_eq!(
+            parameters.with_field("sensor_placing", "outdoor").unwrap(),
+            ListeningParameters::default().with_sensor_placing(SensorPlacing::Outdoor)
+        );
+        assert_eq!(
+            parameters.with_field("tuning_capacitors", "3").unwrap(),
+            ListeningParameters::default()
+                .with_sensor_placing(SensorPlacing::Indoor)
+                .with_tuning_capacitors(TuningCapacitors::new(3).unwrap())
+        );
+        assert_eq!(
+            invalid_key(parameters.with_field("ignore_disturbances", "yes")),
+            "listening.ignore_disturbances"
+        );
+        match parameters.with_field("gain", "7") {
+            Err(Error::UnknownKey(key)) => assert_eq!(key, "gain"),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
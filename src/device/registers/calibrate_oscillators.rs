@@ -25,4 +25,8 @@ impl Register for CalibrateOscillators {
     fn default_value(&self) -> u8 {
         0b_0000_0000
     }
+
+    fn is_direct_command(&self) -> bool {
+        true
+    }
 }
@@ -15,7 +15,14 @@ mod minimum_number_of_lightning;
 mod noise_floor_level;
 mod power_down;
 mod preset_default;
+mod single_lightning_energy_lsb;
+mod single_lightning_energy_mmsb;
+mod single_lightning_energy_msb;
 mod spike_rejection;
+mod srco_calibration_done;
+mod srco_calibration_nok;
+mod trco_calibration_done;
+mod trco_calibration_nok;
 mod watchdog_threshold;
 
 pub(crate) use afe_gain_boost::AfeGainBoost;
@@ -33,7 +40,14 @@ pub(crate) use minimum_number_of_lightning::MinimumNumberOfLightning;
 pub(crate) use noise_floor_level::NoiseFloorLevel;
 pub(crate) use power_down::PowerDown;
 pub(crate) use preset_default::PresetDefault;
+pub(crate) use single_lightning_energy_lsb::SingleLightningEnergyLsb;
+pub(crate) use single_lightning_energy_mmsb::SingleLightningEnergyMmsb;
+pub(crate) use single_lightning_energy_msb::SingleLightningEnergyMsb;
 pub(crate) use spike_rejection::SpikeRejection;
+pub(crate) use srco_calibration_done::SrcoCalibrationDone;
+pub(crate) use srco_calibration_nok::SrcoCalibrationNok;
+pub(crate) use trco_calibration_done::TrcoCalibrationDone;
+pub(crate) use trco_calibration_nok::TrcoCalibrationNok;
 pub(crate) use watchdog_threshold::WatchdogThreshold;
 
 pub(crate) trait Register {
@@ -54,10 +68,54 @@ pub(crate) trait Register {
 
     /// Register's default value as defined in the Detailed Register Map (Table 9).
     fn default_value(&self) -> u8;
+
+    /// Whether writing the register triggers a direct command rather than storing a value, such writes must not
+    /// be repeated blindly.
+    fn is_direct_command(&self) -> bool {
+        false
+    }
+
+    /// Whether reading the register clears the interrupt register, such reads must not be repeated blindly
+    /// either. That is the case for every register sharing the address 0x03 with INT, which includes the read of
+    /// a read-modify-write.
+    fn clears_on_read(&self) -> bool {
+        self.address() == 0x03
+    }
+}
+
+/// All registers, ordered by address.
+pub(crate) fn all() -> Vec<Box<dyn Register>> {
+    vec![
+        Box::new(AfeGainBoost),
+        Box::new(PowerDown),
+        Box::new(NoiseFloorLevel),
+        Box::new(WatchdogThreshold),
+        Box::new(ClearStatistics),
+        Box::new(MinimumNumberOfLightning),
+        Box::new(SpikeRejection),
+        Box::new(FrequencyDivisionRationForAntennaTuning),
+        Box::new(MaskDisturber),
+        Box::new(Interrupt),
+        Box::new(SingleLightningEnergyLsb),
+        Box::new(SingleLightningEnergyMsb),
+        Box::new(SingleLightningEnergyMmsb),
+        Box::new(DistanceEstimation),
+        Box::new(DisplayLcoOnIrqPin),
+        Box::new(DisplaySrcoOnIrqPin),
+        Box::new(DisplayTrcoOnIrqPin),
+        Box::new(InternalTuningCapacitors),
+        Box::new(TrcoCalibrationDone),
+        Box::new(TrcoCalibrationNok),
+        Box::new(SrcoCalibrationDone),
+        Box::new(SrcoCalibrationNok),
+        Box::new(PresetDefault),
+        Box::new(CalibrateOscillators),
+    ]
 }
 
 /// Supported access types.
 #[allow(unused)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub(crate) enum Mode {
     Read,
     Write,
@@ -7,7 +7,7 @@ impl Register for NoiseFloorLevel {
     }
 
     fn description(&self) -> &'static str {
-        &"unimplemented!()"
+        &"Noise Floor Level"
     }
 
     fn address(&self) -> u8 {
@@ -25,4 +25,8 @@ impl Register for PresetDefault {
     fn default_value(&self) -> u8 {
         0b_0000_0000
     }
+
+    fn is_direct_command(&self) -> bool {
+        true
+    }
 }
@@ -0,0 +1,28 @@
+use crate::device::registers::{Mode, Register};
+
+pub(crate) struct SingleLightningEnergyLsb;
+impl Register for SingleLightningEnergyLsb {
+    fn name(&self) -> &'static str {
+        &"S_LIG_L"
+    }
+
+    fn description(&self) -> &'static str {
+        &"Energy of the Single Lightning LSBYTE"
+    }
+
+    fn address(&self) -> u8 {
+        0x04
+    }
+
+    fn mode(&self) -> Mode {
+        Mode::Read
+    }
+
+    fn mask(&self) -> u8 {
+        0b_1111_1111
+    }
+
+    fn default_value(&self) -> u8 {
+        0b_0000_0000
+    }
+}
@@ -0,0 +1,28 @@
+use crate::device::registers::{Mode, Register};
+
+pub(crate) struct SingleLightningEnergyMmsb;
+impl Register for SingleLightningEnergyMmsb {
+    fn name(&self) -> &'static str {
+        &"S_LIG_MM"
+    }
+
+    fn description(&self) -> &'static str {
+        &"Energy of the Single Lightning MMSBYTE"
+    }
+
+    fn address(&self) -> u8 {
+        0x06
+    }
+
+    fn mode(&self) -> Mode {
+        Mode::Read
+    }
+
+    fn mask(&self) -> u8 {
+        0b_0001_1111
+    }
+
+    fn default_value(&self) -> u8 {
+        0b_0000_0000
+    }
+}
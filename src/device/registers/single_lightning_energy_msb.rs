@@ -0,0 +1,28 @@
+use crate::device::registers::{Mode, Register};
+
+pub(crate) struct SingleLightningEnergyMsb;
+impl Register for SingleLightningEnergyMsb {
+    fn name(&self) -> &'static str {
+        &"S_LIG_M"
+    }
+
+    fn description(&self) -> &'static str {
+        &"Energy of the Single Lightning MSBYTE"
+    }
+
+    fn address(&self) -> u8 {
+        0x05
+    }
+
+    fn mode(&self) -> Mode {
+        Mode::Read
+    }
+
+    fn mask(&self) -> u8 {
+        0b_1111_1111
+    }
+
+    fn default_value(&self) -> u8 {
+        0b_0000_0000
+    }
+}
@@ -0,0 +1,28 @@
+use crate::device::registers::{Mode, Register};
+
+pub(crate) struct SrcoCalibrationDone;
+impl Register for SrcoCalibrationDone {
+    fn name(&self) -> &'static str {
+        &"SRCO_CALIB_DONE"
+    }
+
+    fn description(&self) -> &'static str {
+        &"Calibration of SRCO done (1=successful)"
+    }
+
+    fn address(&self) -> u8 {
+        0x3B
+    }
+
+    fn mode(&self) -> Mode {
+        Mode::Read
+    }
+
+    fn mask(&self) -> u8 {
+        0b_1000_0000
+    }
+
+    fn default_value(&self) -> u8 {
+        0b_0
+    }
+}
@@ -0,0 +1,28 @@
+use crate::device::registers::{Mode, Register};
+
+pub(crate) struct SrcoCalibrationNok;
+impl Register for SrcoCalibrationNok {
+    fn name(&self) -> &'static str {
+        &"SRCO_CALIB_NOK"
+    }
+
+    fn description(&self) -> &'static str {
+        &"Calibration of SRCO unsuccessful (1=not successful)"
+    }
+
+    fn address(&self) -> u8 {
+        0x3B
+    }
+
+    fn mode(&self) -> Mode {
+        Mode::Read
+    }
+
+    fn mask(&self) -> u8 {
+        0b_0100_0000
+    }
+
+    fn default_value(&self) -> u8 {
+        0b_0
+    }
+}
@@ -0,0 +1,363 @@
+use crate::device::registers::{
+    self, DisplayLcoOnIrqPin, DisplaySrcoOnIrqPin, DisplayTrcoOnIrqPin,
+    FrequencyDivisionRationForAntennaTuning, InternalTuningCapacitors, Mode, Register,
+};
+use crate::{Error, ListeningParameters, Result, State, TuningCapacitors, AS3935};
+use rppal::gpio::{Level, Trigger};
+use std::cmp;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread::sleep;
+use std::time::Duration;
+
+/// Resonance frequency the antenna has to be tuned to, the datasheet allows ±3.5 %.
+pub const ANTENNA_RESONANCE_FREQUENCY: f64 = 500_000.0;
+pub const ANTENNA_RESONANCE_TOLERANCE: f64 = 0.035;
+/// Nominal frequency of the system RC oscillator.
+pub const SRCO_FREQUENCY: f64 = 1_100_000.0;
+/// Nominal frequency of the timer RC oscillator.
+pub const TRCO_FREQUENCY: f64 = 32_768.0;
+
+/// Oscillator which can be displayed on the IRQ pin.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Oscillator {
+    /// Antenna LC oscillator, displayed divided by LCO_FDIV.
+    Lco,
+    /// System RC oscillator.
+    Srco,
+    /// Timer RC oscillator.
+    Trco,
+}
+
+impl Oscillator {
+    fn display_register(self) -> Box<dyn Register> {
+        match self {
+            Oscillator::Lco => Box::new(DisplayLcoOnIrqPin),
+            Oscillator::Srco => Box::new(DisplaySrcoOnIrqPin),
+            Oscillator::Trco => Box::new(DisplayTrcoOnIrqPin),
+        }
+    }
+}
+
+/// Counts the rising edges of the IRQ pin while an oscillator is displayed on it, see
+/// [`AS3935::with_edge_counter`].
+///
+/// Implemented for closures taking the measurement window, e.g. to read a hardware counter or to simulate
+/// the oscillator.
+pub trait EdgeCounter: Send {
+    fn count_edges(&mut self, window: Duration) -> Result<u64>;
+}
+
+impl<F: FnMut(Duration) -> Result<u64> + Send> EdgeCounter for F {
+    fn count_edges(&mut self, window: Duration) -> Result<u64> {
+        self(window)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct OscillatorMeasurement {
+    pub oscillator: Oscillator,
+    /// Measured frequency on the IRQ pin, in Hz.
+    pub frequency: f64,
+    /// Frequency expected on the IRQ pin, in Hz.
+    pub nominal: f64,
+}
+
+impl OscillatorMeasurement {
+    /// Relative deviation of the measured frequency from the nominal one.
+    pub fn deviation(&self) -> f64 {
+        (self.frequency - self.nominal) / self.nominal
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct RegisterValue {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub address: u8,
+    pub mask: u8,
+    pub value: u8,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct SelfTestCheck {
+    pub name: &'static str,
+    pub passed: bool,
+    pub detail: String,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct SelfTest {
+    pub checks: Vec<SelfTestCheck>,
+}
+
+impl SelfTest {
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(|check| check.passed)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct TuningMeasurement {
+    pub tuning_capacitors: TuningCapacitors,
+    /// Measured antenna resonance frequency, in Hz.
+    pub frequency: f64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct AntennaTuning {
+    pub best: TuningMeasurement,
+    pub measurements: Vec<TuningMeasurement>,
+}
+
+impl AntennaTuning {
+    /// Relative deviation of the best measurement from the required resonance frequency.
+    pub fn deviation(&self) -> f64 {
+        (self.best.frequency - ANTENNA_RESONANCE_FREQUENCY) / ANTENNA_RESONANCE_FREQUENCY
+    }
+
+    pub fn is_within_tolerance(&self) -> bool {
+        self.deviation().abs() <= ANTENNA_RESONANCE_TOLERANCE
+    }
+}
+
+impl AS3935 {
+    /// Counts the IRQ pin edges with `counter` instead of GPIO interrupts, which can't keep up with the SRCO.
+    pub fn with_edge_counter<C: EdgeCounter + 'static>(mut self, counter: C) -> Self {
+        self.edge_counter = Some(Box::new(counter));
+        self
+    }
+
+    /// Reads all readable registers.
+    ///
+    /// Note that reading the interrupt register clears a pending interrupt.
+    pub fn dump(&mut self) -> Result<Vec<RegisterValue>> {
+        let mut interface = self.interface.lock()?;
+
+        registers::all()
+            .into_iter()
+            .filter(|register| register.mode() != Mode::Write)
+            .map(|register| {
+                Ok(RegisterValue {
+                    name: register.name(),
+                    description: register.description(),
+                    address: register.address(),
+                    mask: register.mask(),
+                    value: interface.read(register)?,
+                })
+            })
+            .collect()
+    }
+
+    /// Resets the sensor to defaults, verifies that the registers read back their default values and that
+    /// the RC oscillators can be calibrated, then restores the parameters of the sensor.
+    pub fn self_test(&mut self) -> Result<SelfTest> {
+        self.assert_state(&self.state, &[State::StandingBy, State::PoweredDown])?;
+
+        let mut checks = Vec::new();
+
+        self.power_up()?;
+        self.state = State::StandingBy;
+        self.configure_defaults()?;
+
+        let mismatches = {
+            let mut interface = self.interface.lock()?;
+            let mut mismatches = Vec::new();
+
+            for register in registers::all() {
+                if register.mode() != Mode::ReadWrite {
+                    continue;
+                }
+
+                let (name, default_value) = (register.name(), register.default_value());
+
+                match interface.read(register) {
+                    Ok(value) if value == default_value => {}
+                    Ok(value) => mismatches.push(format!(
+                        "{} = {:#b} (expected {:#b})",
+                        name, value, default_value
+                    )),
+                    Err(e) => mismatches.push(format!("{}: {}", name, e)),
+                }
+            }
+
+            mismatches
+        };
+
+        checks.push(SelfTestCheck {
+            name: "register defaults",
+            passed: mismatches.is_empty(),
+            detail: if mismatches.is_empty() {
+                String::from("all registers read back their default values")
+            } else {
+                mismatches.join(", ")
+            },
+        });
+
+        self.calibrate_clock()?;
+        let calibration = self.calibration_status()?;
+
+        checks.push(SelfTestCheck {
+            name: "oscillators calibration",
+            passed: calibration.is_successful(),
+            detail: format!(
+                "TRCO {}, SRCO {}",
+                if calibration.trco { "ok" } else { "failed" },
+                if calibration.srco { "ok" } else { "failed" },
+            ),
+        });
+
+        let parameters = self.parameters();
+        self.configure_listen_parameters(parameters)?;
+
+        Ok(SelfTest { checks })
+    }
+
+    /// Measures the frequency of `oscillator` by counting its periods on the IRQ pin for the duration of
+    /// `window`.
+    ///
+    /// The edges are counted with GPIO interrupts unless an [`EdgeCounter`] was set; the LCO is divided by
+    /// LCO_FDIV, but the SRCO and TRCO are too fast for them and a polled IRQ source samples levels instead
+    /// of counting edges. Without an edge counter, measuring the SRCO or TRCO or measuring through a polled
+    /// IRQ source therefore fails with [`Error::InvalidArgument`], as does an empty `window`.
+    pub fn measure_oscillator(
+        &mut self,
+        oscillator: Oscillator,
+        window: Duration,
+    ) -> Result<OscillatorMeasurement> {
+        self.assert_state(&self.state, &[State::StandingBy])?;
+
+        if window == Duration::from_secs(0) {
+            return Err(Error::InvalidArgument(
+                "measurement window must not be zero",
+            ));
+        }
+
+        if self.edge_counter.is_none() {
+            if oscillator != Oscillator::Lco {
+                return Err(Error::InvalidArgument(
+                    "the SRCO and TRCO can only be measured with an edge counter",
+                ));
+            }
+
+            if !self.irq_pin.counts_edges() {
+                return Err(Error::InvalidArgument(
+                    "a polled IRQ source can't count edges, an edge counter is required",
+                ));
+            }
+        }
+
+        let nominal = match oscillator {
+            Oscillator::Lco => {
+                let ratio = self
+                    .interface
+                    .lock()?
+                    .read(Box::new(FrequencyDivisionRationForAntennaTuning))?;
+
+                ANTENNA_RESONANCE_FREQUENCY / f64::from(16_u32 << ratio)
+            }
+            Oscillator::Srco => SRCO_FREQUENCY,
+            Oscillator::Trco => TRCO_FREQUENCY,
+        };
+
+        self.interface
+            .lock()?
+            .write(oscillator.display_register(), 0b_1)?;
+
+        let edges = self.count_edges(window);
+
+        self.interface
+            .lock()?
+            .write(oscillator.display_register(), 0b_0)?;
+
+        Ok(OscillatorMeasurement {
+            oscillator,
+            frequency: edges? as f64 / window.as_secs_f64(),
+            nominal,
+        })
+    }
+
+    /// Measures the antenna resonance frequency for every tuning capacitors setting by counting LCO periods
+    /// on the IRQ pin for the duration of `window`, then configures the setting closest to 500 kHz.
+    pub fn tune_antenna(&mut self, window: Duration) -> Result<AntennaTuning> {
+        self.assert_state(&self.state, &[State::StandingBy])?;
+
+        if window == Duration::from_secs(0) {
+            return Err(Error::InvalidArgument(
+                "measurement window must not be zero",
+            ));
+        }
+
+        let mut measurements = Vec::new();
+
+        for value in 0..=15 {
+            let tuning_capacitors = TuningCapacitors(value);
+
+            self.interface
+                .lock()?
+                .write(Box::new(InternalTuningCapacitors), value)?;
+
+            let lco = self.measure_oscillator(Oscillator::Lco, window)?;
+
+            measurements.push(TuningMeasurement {
+                tuning_capacitors,
+                frequency: lco.frequency / lco.nominal * ANTENNA_RESONANCE_FREQUENCY,
+            });
+        }
+
+        let best = *measurements
+            .iter()
+            .min_by(|a, b| {
+                // a measurement which is not a number is never the best one
+                let deviation = |m: &TuningMeasurement| {
+                    let deviation = (m.frequency - ANTENNA_RESONANCE_FREQUENCY).abs();
+
+                    if deviation.is_nan() {
+                        f64::INFINITY
+                    } else {
+                        deviation
+                    }
+                };
+
+                deviation(a)
+                    .partial_cmp(&deviation(b))
+                    .unwrap_or(cmp::Ordering::Equal)
+            })
+            .unwrap();
+
+        self.configure(
+            ListeningParameters::default().with_tuning_capacitors(best.tuning_capacitors),
+        )?;
+
+        Ok(AntennaTuning { best, measurements })
+    }
+
+    fn count_edges(&mut self, window: Duration) -> Result<u64> {
+        if let Some(counter) = &mut self.edge_counter {
+            return counter.count_edges(window);
+        }
+
+        let edges = Arc::new(AtomicU64::new(0));
+        let counter = edges.clone();
+
+        self.irq_pin.set_async_interrupt(
+            Trigger::RisingEdge,
+            Box::new(move |_level: Level| {
+                counter.fetch_add(1, Ordering::Relaxed);
+            }),
+        )?;
+
+        sleep(window);
+
+        self.irq_pin.clear_async_interrupt()?;
+
+        Ok(edges.load(Ordering::Relaxed))
+    }
+}
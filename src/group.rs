@@ -0,0 +1,367 @@
+use crate::{Event, ListeningParameters, State, AS3935};
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+/// Lightning, disturbers or noise reported by different sensors within this window are flagged as coincident.
+pub const DEFAULT_COINCIDENCE_WINDOW: Duration = Duration::from_millis(100);
+
+pub type Result<T> = ::std::result::Result<T, Error>;
+
+/// Failure of a single sensor of the group.
+#[derive(Debug)]
+pub struct Error {
+    pub sensor: String,
+    pub error: crate::Error,
+}
+
+impl ::std::error::Error for Error {}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> ::std::result::Result<(), fmt::Error> {
+        write!(f, "sensor {}: {}", self.sensor, self.error)
+    }
+}
+
+/// Event of the merged stream of a [`SensorGroup`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct TaggedEvent {
+    pub sensor: String,
+    pub event: Event,
+    pub received_at: SystemTime,
+    /// Other sensors which reported an event of the same kind within the coincidence window before this one,
+    /// always empty for status events.
+    pub coincident_with: Vec<String>,
+}
+
+impl TaggedEvent {
+    pub fn is_coincident(&self) -> bool {
+        !self.coincident_with.is_empty()
+    }
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct SensorHealth {
+    pub listening: bool,
+    pub events: u64,
+    pub last_event: Option<SystemTime>,
+    pub last_error: Option<String>,
+}
+
+struct Member {
+    id: String,
+    sensor: AS3935,
+    parameters: ListeningParameters,
+    health: Arc<Mutex<SensorHealth>>,
+}
+
+/// Owns several sensors, starts and stops them together and merges their events into one stream.
+pub struct SensorGroup {
+    members: Vec<Member>,
+    coincidence_window: Duration,
+    stopping: Arc<AtomicBool>,
+}
+
+impl Default for SensorGroup {
+    fn default() -> Self {
+        Self {
+            members: Vec::new(),
+            coincidence_window: DEFAULT_COINCIDENCE_WINDOW,
+            stopping: Arc::new(AtomicBool::new(false)),
+        }
+    }
+}
+
+impl SensorGroup {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a sensor which listens with `parameters`, `id` tags its events and has to be unique within the group.
+    pub fn with_sensor<S: Into<String>>(
+        mut self,
+        id: S,
+        sensor: AS3935,
+        parameters: ListeningParameters,
+    ) -> Self {
+        self.members.push(Member {
+            id: id.into(),
+            sensor,
+            parameters,
+            health: Arc::new(Mutex::new(SensorHealth::default())),
+        });
+        self
+    }
+
+    pub fn with_coincidence_window(mut self, coincidence_window: Duration) -> Self {
+        self.coincidence_window = coincidence_window;
+        self
+    }
+
+    pub fn sensor(&mut self, id: &str) -> Option<&mut AS3935> {
+        self.members
+            .iter_mut()
+            .find(|member| member.id == id)
+            .map(|member| &mut member.sensor)
+    }
+
+    pub fn health(&self) -> Vec<(String, SensorHealth)> {
+        self.members
+            .iter()
+            .map(|member| (member.id.clone(), member.health.lock().unwrap().clone()))
+            .collect()
+    }
+
+    /// Starts all the sensors, or none of them if any of them fails to start.
+    pub fn listen(&mut self) -> Result<Receiver<TaggedEvent>> {
+        let stopping = Arc::new(AtomicBool::new(false));
+        let (merged_sender, merged_receiver) = channel::<(usize, Event, Instant, SystemTime)>();
+        let mut streams = Vec::new();
+
+        for index in 0..self.members.len() {
+            let member = &mut self.members[index];
+
+            match member.sensor.listen(member.parameters) {
+                Ok(events) => streams.push((index, events)),
+                Err(error) => {
+                    member.health.lock().unwrap().last_error = Some(error.to_string());
+                    let failed = member.id.clone();
+
+                    for (started, _) in streams {
+                        let _ = self.members[started].sensor.terminate();
+                    }
+
+                    return Err(Error {
+                        sensor: failed,
+                        error,
+                    });
+                }
+            }
+        }
+
+        for (index, events) in streams {
+            let member = &self.members[index];
+            {
+                let mut health = member.health.lock().unwrap();
+                health.listening = true;
+                health.last_error = None;
+            }
+
+            let id = member.id.clone();
+            let health = member.health.clone();
+            let stopping = stopping.clone();
+            let merged_sender = merged_sender.clone();
+
+            thread::spawn(move || {
+                forward(index, &events, &merged_sender, &health);
+
+                let mut health = health.lock().unwrap();
+                health.listening = false;
+                if !stopping.load(Ordering::SeqCst) {
+                    error!("sensor {} stopped reporting events", id);
+                    health.last_error = Some(String::from("event stream closed unexpectedly"));
+                }
+            });
+        }
+
+        let ids = self
+            .members
+            .iter()
+            .map(|member| member.id.clone())
+            .collect::<Vec<_>>();
+        let (sender, receiver) = channel::<TaggedEvent>();
+        let window = self.coincidence_window;
+
+        thread::spawn(move || merge(&ids, window, &merged_receiver, &sender));
+
+        self.stopping = stopping;
+
+        Ok(receiver)
+    }
+
+    /// Stops all the listening and paused sensors, returns the first error after attempting to stop every one
+    /// of them.
+    pub fn terminate(&mut self) -> Result<()> {
+        self.stopping.store(true, Ordering::SeqCst);
+
+        let mut result = Ok(());
+
+        for member in &mut self.members {
+            let state = member.sensor.state;
+
+            if state != State::Listening && state != State::Paused {
+                continue;
+            }
+
+            if let Err(error) = member.sensor.terminate() {
+                member.health.lock().unwrap().last_error = Some(error.to_string());
+
+                if result.is_ok() {
+                    result = Err(Error {
+                        sensor: member.id.clone(),
+                        error,
+                    });
+                }
+            }
+        }
+
+        result
+    }
+}
+
+fn forward(
+    index: usize,
+    events: &Receiver<Event>,
+    merged: &Sender<(usize, Event, Instant, SystemTime)>,
+    health: &Mutex<SensorHealth>,
+) {
+    for event in events {
+        let received_at = SystemTime::now();
+
+        {
+            let mut health = health.lock().unwrap();
+            health.events += 1;
+            health.last_event = Some(received_at);
+        }
+
+        if merged
+            .send((index, event, Instant::now(), received_at))
+            .is_err()
+        {
+            return;
+        }
+    }
+}
+
+fn merge(
+    ids: &[String],
+    window: Duration,
+    merged: &Receiver<(usize, Event, Instant, SystemTime)>,
+    sender: &Sender<TaggedEvent>,
+) {
+    let mut recent: VecDeque<(usize, Event, Instant)> = VecDeque::new();
+
+    for (index, event, instant, received_at) in merged {
+        while let Some((_, _, earliest)) = recent.front() {
+            if instant.saturating_duration_since(*earliest) <= window {
+                break;
+            }
+
+            recent.pop_front();
+        }
+
+        let mut coincident_with = Vec::new();
+        for (other, other_event, _) in &recent {
+            if *other != index
+                && coincides(other_event, &event)
+                && !coincident_with.contains(&ids[*other])
+            {
+                coincident_with.push(ids[*other].clone());
+            }
+        }
+
+        recent.push_back((index, event, instant));
+
+        let tagged = TaggedEvent {
+            sensor: ids[index].clone(),
+            event,
+            received_at,
+            coincident_with,
+        };
+
+        if sender.send(tagged).is_err() {
+            return;
+        }
+    }
+}
+
+/// Whether the events are detections of the same kind, status events concern one sensor and never coincide.
+fn coincides(event: &Event, other: &Event) -> bool {
+    matches!(
+        (event, other),
+        (Event::Lightning(..), Event::Lightning(..))
+            | (Event::Disturbance, Event::Disturbance)
+            | (Event::Noise, Event::Noise)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{HeadOfStormDistance, LightningEnergy, Status};
+
+    /// Merges the events of sensors `a` and `b`, which are reported a millisecond apart.
+    fn merged(events: &[(usize, Event)], window: Duration) -> Vec<TaggedEvent> {
+        let ids = vec![String::from("a"), String::from("b")];
+        let (input, merged) = channel();
+        let (sender, output) = channel();
+        let started = Instant::now();
+
+        for (n, &(index, event)) in events.iter().enumerate() {
+            let instant = started + Duration::from_millis(n as u64);
+            input
+                .send((index, event, instant, SystemTime::now()))
+                .unwrap();
+        }
+        drop(input);
+
+        merge(&ids, window, &merged, &sender);
+        drop(sender);
+
+        output.iter().collect()
+    }
+
+    #[test]
+    fn flags_detections_of_the_same_kind_by_other_sensors() {
+        let lightning = Event::Lightning(HeadOfStormDistance::Kilometers(12), LightningEnergy(1));
+        let tagged = merged(
+            &[
+                (0, lightning),
+                (0, Event::Noise),
+                (1, Event::Disturbance),
+                (1, lightning),
+                (1, Event::Noise),
+            ],
+            Duration::from_secs(60),
+        );
+
+        let coincident: Vec<&[String]> = tagged
+            .iter()
+            .map(|tagged| tagged.coincident_with.as_slice())
+            .collect();
+        let a = [String::from("a")];
+        assert_eq!(coincident, vec![&[][..], &[], &[], &a, &a]);
+    }
+
+    #[test]
+    fn status_events_never_coincide() {
+        let unavailable = Event::Status(Status::SensorUnavailable);
+        let tagged = merged(
+            &[
+                (0, unavailable),
+                (1, unavailable),
+                (0, Event::Status(Status::SensorReset)),
+                (1, Event::Status(Status::SensorReset)),
+            ],
+            Duration::from_secs(60),
+        );
+
+        assert!(tagged.iter().all(|tagged| !tagged.is_coincident()));
+    }
+
+    #[test]
+    fn events_outside_the_window_do_not_coincide() {
+        let tagged = merged(
+            &[(0, Event::Noise), (1, Event::Noise)],
+            Duration::from_micros(999),
+        );
+
+        assert!(tagged.iter().all(|tagged| !tagged.is_coincident()));
+    }
+}
@@ -0,0 +1,44 @@
+use crate::{Event, ListeningParameters, Result, State, AS3935};
+use std::sync::mpsc::Receiver;
+
+impl AS3935 {
+    /// Like [`listen`](AS3935::listen), but the listening stops as soon as the returned guard is dropped, also
+    /// on early returns and panics.
+    pub fn listen_guarded(&mut self, parameters: ListeningParameters) -> Result<ListenGuard<'_>> {
+        let events = self.listen(parameters)?;
+
+        Ok(ListenGuard {
+            sensor: self,
+            events,
+        })
+    }
+}
+
+/// Stops the listening once dropped, leaving the sensor standing by, or sleeping if it was put to sleep.
+pub struct ListenGuard<'a> {
+    sensor: &'a mut AS3935,
+    events: Receiver<Event>,
+}
+
+impl<'a> ListenGuard<'a> {
+    pub fn events(&self) -> &Receiver<Event> {
+        &self.events
+    }
+
+    pub fn sensor(&mut self) -> &mut AS3935 {
+        self.sensor
+    }
+}
+
+impl<'a> Drop for ListenGuard<'a> {
+    fn drop(&mut self) {
+        // the listening may have been terminated through the guard already
+        if self.sensor.state != State::Listening && self.sensor.state != State::Paused {
+            return;
+        }
+
+        if let Err(e) = self.sensor.stop_listening() {
+            warn!("failed to stop listening: {}", e);
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use crate::{
+    Error, Event, HeadOfStormDistance, LightningEnergy, ListeningParameters, Result, State, Status,
+    AS3935,
+};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// How often the [`Dispatcher`] checks whether it was stopped while no events arrive.
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Receives the events of a sensor, see [`Dispatcher`].
+///
+/// Every method gets the sensor, so that the handler can e.g. [`configure`](AS3935::configure) it in response.
+/// Errors returned by the handlers are passed to [`on_error`](EventHandler::on_error).
+pub trait EventHandler: Send {
+    fn on_lightning(
+        &mut self,
+        _sensor: &mut AS3935,
+        _distance: HeadOfStormDistance,
+        _energy: LightningEnergy,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    fn on_disturber(&mut self, _sensor: &mut AS3935) -> Result<()> {
+        Ok(())
+    }
+
+    fn on_noise(&mut self, _sensor: &mut AS3935) -> Result<()> {
+        Ok(())
+    }
+
+    fn on_status(&mut self, _sensor: &mut AS3935, _status: Status) -> Result<()> {
+        Ok(())
+    }
+
+    fn on_error(&mut self, _sensor: &mut AS3935, error: Error) {
+        error!("event handler failed: {}", error);
+    }
+}
+
+/// Delivers the events of a listening sensor to an [`EventHandler`].
+///
+/// The handler is called from a worker thread which owns the sensor until stopped, never from the IRQ handler.
+pub struct Dispatcher;
+
+impl Dispatcher {
+    /// Starts listening and dispatches the events until stopped, or until the handler terminates the sensor.
+    pub fn spawn<H: EventHandler + 'static>(
+        mut sensor: AS3935,
+        parameters: ListeningParameters,
+        handler: H,
+    ) -> Result<DispatcherHandle> {
+        let events = sensor.listen(parameters)?;
+        let stopping = Arc::new(AtomicBool::new(false));
+
+        let thread = {
+            let stopping = stopping.clone();
+            thread::spawn(move || run(sensor, handler, &events, &stopping))
+        };
+
+        Ok(DispatcherHandle { stopping, thread })
+    }
+}
+
+fn run<H: EventHandler>(
+    mut sensor: AS3935,
+    mut handler: H,
+    events: &Receiver<Event>,
+    stopping: &AtomicBool,
+) -> AS3935 {
+    while !stopping.load(Ordering::SeqCst) {
+        let event = match events.recv_timeout(CHECK_INTERVAL) {
+            Ok(event) => event,
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => {
+                if sensor.is_listening() {
+                    error!("sensor stopped reporting events");
+                }
+                break;
+            }
+        };
+
+        let result = match event {
+            Event::Lightning(distance, energy) => {
+                handler.on_lightning(&mut sensor, distance, energy)
+            }
+            Event::Disturbance => handler.on_disturber(&mut sensor),
+            Event::Noise => handler.on_noise(&mut sensor),
+            Event::Status(status) => handler.on_status(&mut sensor, status),
+        };
+
+        if let Err(error) = result {
+            handler.on_error(&mut sensor, error);
+        }
+    }
+
+    // the handler may have terminated the sensor already
+    if sensor.state == State::Listening || sensor.state == State::Paused {
+        if let Err(e) = sensor.terminate() {
+            error!("failed to terminate the sensor: {}", e);
+        }
+    }
+
+    sensor
+}
+
+pub struct DispatcherHandle {
+    stopping: Arc<AtomicBool>,
+    thread: JoinHandle<AS3935>,
+}
+
+impl DispatcherHandle {
+    /// Stops the dispatching and the listening, returns the powered down sensor.
+    pub fn stop(self) -> AS3935 {
+        self.stopping.store(true, Ordering::SeqCst);
+
+        match self.thread.join() {
+            Ok(sensor) => sensor,
+            Err(panic) => ::std::panic::resume_unwind(panic),
+        }
+    }
+}
@@ -1,25 +1,29 @@
 use crate::interface::i2c::I2cAddress;
 use crate::interface::Irq;
 use crate::{
-    HeadOfStormDistance, IgnoreDisturbances, MinimumLightningThreshold, NoiseFloorThreshold,
-    SensorPlacing, SignalVerificationThreshold,
+    AfeGain, HeadOfStormDistance, IgnoreDisturbances, LightningEnergy, MinimumLightningThreshold,
+    NoiseFloorThreshold, SensorPlacing, SignalVerificationThreshold, SpikeRejectionThreshold,
+    TuningCapacitors,
 };
+use std::convert::TryFrom;
 
-impl From<u8> for Irq {
-    fn from(irq: u8) -> Self {
+impl TryFrom<u8> for Irq {
+    type Error = u8;
+
+    fn try_from(irq: u8) -> ::std::result::Result<Self, Self::Error> {
         match irq {
-            0b_0000 => Irq::DistanceEstimationChanged,
-            0b_0001 => Irq::NoiseLevelTooHigh,
-            0b_0100 => Irq::DisturberDetected,
-            0b_1000 => Irq::Lightning,
-            _ => panic!(),
+            0b_0000 => Ok(Irq::DistanceEstimationChanged),
+            0b_0001 => Ok(Irq::NoiseLevelTooHigh),
+            0b_0100 => Ok(Irq::DisturberDetected),
+            0b_1000 => Ok(Irq::Lightning),
+            _ => Err(irq),
         }
     }
 }
 
-impl Into<u8> for MinimumLightningThreshold {
-    fn into(self) -> u8 {
-        match self {
+impl From<MinimumLightningThreshold> for u8 {
+    fn from(threshold: MinimumLightningThreshold) -> Self {
+        match threshold {
             MinimumLightningThreshold::One => 0b_00_u8,
             MinimumLightningThreshold::Five => 0b_01_u8,
             MinimumLightningThreshold::Nine => 0b_10_u8,
@@ -28,9 +32,22 @@ impl Into<u8> for MinimumLightningThreshold {
     }
 }
 
-impl From<u8> for HeadOfStormDistance {
-    fn from(raw_distance: u8) -> Self {
-        match raw_distance {
+impl From<u8> for MinimumLightningThreshold {
+    fn from(raw: u8) -> Self {
+        match raw {
+            0b_00 => MinimumLightningThreshold::One,
+            0b_01 => MinimumLightningThreshold::Five,
+            0b_10 => MinimumLightningThreshold::Nine,
+            _ => MinimumLightningThreshold::Sixteen,
+        }
+    }
+}
+
+impl TryFrom<u8> for HeadOfStormDistance {
+    type Error = u8;
+
+    fn try_from(raw_distance: u8) -> ::std::result::Result<Self, Self::Error> {
+        Ok(match raw_distance {
             0b_11_1111 => HeadOfStormDistance::OutOfRange,
             0b_10_1000 => HeadOfStormDistance::Kilometers(40),
             0b_10_0101 => HeadOfStormDistance::Kilometers(37),
@@ -47,43 +64,75 @@ impl From<u8> for HeadOfStormDistance {
             0b_00_0110 => HeadOfStormDistance::Kilometers(6),
             0b_00_0101 => HeadOfStormDistance::Kilometers(5),
             0b_00_0001 => HeadOfStormDistance::Overhead,
-            _ => panic!(),
-        }
+            _ => return Err(raw_distance),
+        })
     }
 }
 
-impl Into<u8> for SensorPlacing {
-    fn into(self) -> u8 {
-        match self {
-            SensorPlacing::Indoor => 0b_1_0010_u8,
-            SensorPlacing::Outdoor => 0b_0_1110_u8,
-        }
+impl From<[u8; 3]> for LightningEnergy {
+    fn from(bytes: [u8; 3]) -> Self {
+        let [mmsb, msb, lsb] = bytes;
+
+        LightningEnergy(u32::from(mmsb) << 16 | u32::from(msb) << 8 | u32::from(lsb))
+    }
+}
+
+impl From<SensorPlacing> for u8 {
+    fn from(sensor_placing: SensorPlacing) -> Self {
+        AfeGain::from(sensor_placing).into()
     }
 }
 
-impl Into<u8> for SignalVerificationThreshold {
-    fn into(self) -> u8 {
-        self.0
+impl From<AfeGain> for u8 {
+    fn from(afe_gain: AfeGain) -> Self {
+        afe_gain.0
     }
 }
 
-impl Into<u8> for NoiseFloorThreshold {
-    fn into(self) -> u8 {
-        self.0
+impl From<SignalVerificationThreshold> for u8 {
+    fn from(threshold: SignalVerificationThreshold) -> Self {
+        threshold.0
     }
 }
 
-impl Into<u8> for IgnoreDisturbances {
-    fn into(self) -> u8 {
-        match self {
+impl From<SpikeRejectionThreshold> for u8 {
+    fn from(threshold: SpikeRejectionThreshold) -> Self {
+        threshold.0
+    }
+}
+
+impl From<NoiseFloorThreshold> for u8 {
+    fn from(threshold: NoiseFloorThreshold) -> Self {
+        threshold.0
+    }
+}
+
+impl From<TuningCapacitors> for u8 {
+    fn from(tuning_capacitors: TuningCapacitors) -> Self {
+        tuning_capacitors.0
+    }
+}
+
+impl From<IgnoreDisturbances> for u8 {
+    fn from(ignore_disturbances: IgnoreDisturbances) -> Self {
+        match ignore_disturbances {
             IgnoreDisturbances::Yes => 0b_1,
             IgnoreDisturbances::No => 0b_0,
         }
     }
 }
 
-impl Into<u16> for I2cAddress {
-    fn into(self) -> u16 {
-        return self.0 as u16;
+impl From<u8> for IgnoreDisturbances {
+    fn from(raw: u8) -> Self {
+        match raw {
+            0b_0 => IgnoreDisturbances::No,
+            _ => IgnoreDisturbances::Yes,
+        }
+    }
+}
+
+impl From<I2cAddress> for u16 {
+    fn from(address: I2cAddress) -> Self {
+        u16::from(address.0)
     }
 }
@@ -1,9 +1,13 @@
 use crate::interface::Interface;
 use crate::interface::{calculate_bitshift, Result};
 use rppal::i2c::I2c;
+use std::sync::{Arc, Mutex, PoisonError};
 
 pub const DEFAULT_I2C_ADDRESS: u8 = 0x03;
 
+/// I²C bus shared with other drivers, the sensor locks it for each transaction and sets its address in it.
+pub type SharedI2c = Arc<Mutex<I2c>>;
+
 pub struct I2cAddress(pub(crate) u8);
 
 impl I2cAddress {
@@ -21,14 +25,16 @@ impl I2cAddress {
 }
 
 pub(crate) struct I2cInterface {
-    i2c: I2c,
+    i2c: SharedI2c,
+    address: u16,
 }
 
 impl I2cInterface {
-    pub(crate) fn new(mut i2c: I2c, i2c_address: I2cAddress) -> Result<Self> {
-        i2c.set_slave_address(i2c_address.into())?;
-
-        Ok(Self { i2c })
+    pub(crate) fn new(i2c: SharedI2c, i2c_address: I2cAddress) -> Self {
+        Self {
+            i2c,
+            address: i2c_address.into(),
+        }
     }
 }
 
@@ -36,7 +42,11 @@ impl Interface for I2cInterface {
     fn read(&mut self, register: Box<dyn crate::device::registers::Register>) -> Result<u8> {
         let mut data: [u8; 1] = [0];
 
-        self.i2c.write_read(&[register.address()], &mut data)?;
+        {
+            let mut i2c = self.i2c.lock().unwrap_or_else(PoisonError::into_inner);
+            i2c.set_slave_address(self.address)?;
+            i2c.write_read(&[register.address()], &mut data)?;
+        }
 
         let value = (data[0] & register.mask()) >> calculate_bitshift(register.mask());
         debug!("read {} = {:#b}", register.name(), value);
@@ -54,13 +64,16 @@ impl Interface for I2cInterface {
         let bitshift = calculate_bitshift(register.mask());
         assert!(payload <= (register.mask() >> bitshift));
 
+        // other drivers on the bus must not interleave between the read and the write
+        let mut i2c = self.i2c.lock().unwrap_or_else(PoisonError::into_inner);
+        i2c.set_slave_address(self.address)?;
+
         let mut current_data: [u8; 1] = [0];
-        self.i2c
-            .write_read(&[register.address()], &mut current_data)?;
+        i2c.write_read(&[register.address()], &mut current_data)?;
 
-        self.i2c.write(&[
+        i2c.write(&[
             register.address(),
-            (current_data[0] ^ register.mask()) | (payload << bitshift),
+            (current_data[0] & !register.mask()) | (payload << bitshift),
         ])?;
 
         Ok(())
@@ -1,14 +1,32 @@
+use rppal::gpio::{InputPin, Level, Trigger};
 use std::fmt::{Display, Formatter};
-use std::time::Duration;
+use std::mem;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard, PoisonError, TryLockError};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
 
 pub(crate) mod conversion;
 pub mod i2c;
+pub mod polling;
 
 pub(crate) const CLOCK_GENERATION_DELAY: Duration = Duration::from_millis(2);
 pub(crate) const IRQ_TRIGGER_TO_READY_DELAY: Duration = Duration::from_millis(2);
 pub(crate) const LIGHTNING_CALCULATION_DELAY: Duration = Duration::from_millis(2);
 pub const DISTURBER_DEACTIVATION_PERIOD: Duration = Duration::from_millis(1500);
 pub const APPROXIMATE_MINIMUM_LIGHTNING_INTERVAL: Duration = Duration::from_secs(1);
+/// How long an access to the sensor waits for another one to finish before failing with
+/// [`Error::Deadlock`](crate::Error::Deadlock).
+pub const DEFAULT_LOCK_TIMEOUT: Duration = Duration::from_secs(5);
+const LOCK_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Bus the sensor is connected to.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Bus {
+    I2c { bus: u8, address: u8 },
+    Spi { bus: u8, chip_select: u8 },
+}
 
 pub(crate) type Result<T> = ::std::result::Result<T, Error>;
 
@@ -16,6 +34,8 @@ pub(crate) type Result<T> = ::std::result::Result<T, Error>;
 pub enum Error {
     Spi(::rppal::spi::Error),
     I2c(::rppal::i2c::Error),
+    /// The access does not match the recording being replayed, or the recorded access failed.
+    Replay(String),
 }
 
 impl ::std::error::Error for Error {}
@@ -25,6 +45,7 @@ impl Display for Error {
         match self {
             Error::Spi(e) => e.fmt(f),
             Error::I2c(e) => e.fmt(f),
+            Error::Replay(e) => write!(f, "replay: {}", e),
         }
     }
 }
@@ -60,9 +81,156 @@ pub(crate) trait Interface: Send {
     ) -> Result<()>;
 }
 
+pub(crate) type IrqCallback = Box<dyn FnMut(Level) + Send>;
+
+/// Source of the IRQ signal of the sensor, usually the GPIO pin it is wired to.
+pub(crate) trait IrqSource: Send {
+    fn set_async_interrupt(&mut self, trigger: Trigger, callback: IrqCallback)
+        -> crate::Result<()>;
+    fn clear_async_interrupt(&mut self) -> crate::Result<()>;
+    /// BCM GPIO pin number, if the source is a GPIO pin.
+    fn pin(&self) -> Option<u8>;
+    /// Whether the callback runs once per edge of the IRQ signal, as needed to count oscillator periods.
+    fn counts_edges(&self) -> bool {
+        true
+    }
+}
+
+impl IrqSource for InputPin {
+    fn set_async_interrupt(
+        &mut self,
+        trigger: Trigger,
+        callback: IrqCallback,
+    ) -> crate::Result<()> {
+        InputPin::set_async_interrupt(self, trigger, callback)?;
+
+        Ok(())
+    }
+
+    fn clear_async_interrupt(&mut self) -> crate::Result<()> {
+        InputPin::clear_async_interrupt(self)?;
+
+        Ok(())
+    }
+
+    fn pin(&self) -> Option<u8> {
+        Some(InputPin::pin(self))
+    }
+}
+
+/// Interface shared by the control calls with the IRQ handler and the background threads.
+pub(crate) struct SharedInterface {
+    interface: Mutex<Box<dyn Interface>>,
+    timeout_micros: AtomicU64,
+    poison_reported: AtomicBool,
+    /// Serializes the oscillators calibrations, which span several accesses and display TRCO meanwhile.
+    calibration: Mutex<()>,
+}
+
+impl SharedInterface {
+    pub(crate) fn new(interface: Box<dyn Interface>) -> Self {
+        Self {
+            interface: Mutex::new(interface),
+            timeout_micros: AtomicU64::new(DEFAULT_LOCK_TIMEOUT.as_micros() as u64),
+            poison_reported: AtomicBool::new(false),
+            calibration: Mutex::new(()),
+        }
+    }
+
+    /// Waits for any other oscillators calibration to finish.
+    pub(crate) fn lock_calibration(&self) -> MutexGuard<'_, ()> {
+        self.calibration
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub(crate) fn set_timeout(&self, timeout: Duration) {
+        self.timeout_micros
+            .store(timeout.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    /// Waits for exclusive access for up to the timeout.
+    ///
+    /// A panic while holding the lock, e.g. in a consumer's code run by the IRQ handler, leaves the interface
+    /// intact, so the poisoning is ignored.
+    pub(crate) fn lock(&self) -> crate::Result<MutexGuard<'_, Box<dyn Interface>>> {
+        let timeout = Duration::from_micros(self.timeout_micros.load(Ordering::Relaxed));
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            match self.interface.try_lock() {
+                Ok(interface) => return Ok(interface),
+                Err(TryLockError::Poisoned(poisoned)) => {
+                    if !self.poison_reported.swap(true, Ordering::Relaxed) {
+                        warn!("recovering the sensor interface after a panic while it was in use");
+                    }
+
+                    return Ok(poisoned.into_inner());
+                }
+                Err(TryLockError::WouldBlock) if Instant::now() < deadline => {
+                    sleep(LOCK_POLL_INTERVAL)
+                }
+                Err(TryLockError::WouldBlock) => {
+                    error!("sensor interface still in use after {:?}", timeout);
+                    return Err(crate::Error::Deadlock);
+                }
+            }
+        }
+    }
+
+    /// Wraps the interface, waiting for it however long it's in use.
+    pub(crate) fn decorate<F>(&self, decorate: F)
+    where
+        F: FnOnce(Box<dyn Interface>) -> Box<dyn Interface>,
+    {
+        let mut interface = self
+            .interface
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let decorated = mem::replace(&mut *interface, Box::new(Detached));
+
+        *interface = decorate(decorated);
+    }
+}
+
+/// Stands in for an interface or an IRQ source while it is being wrapped in a decorator, it is never accessed.
+pub(crate) struct Detached;
+
+impl IrqSource for Detached {
+    fn set_async_interrupt(
+        &mut self,
+        _trigger: Trigger,
+        _callback: IrqCallback,
+    ) -> crate::Result<()> {
+        unreachable!()
+    }
+
+    fn clear_async_interrupt(&mut self) -> crate::Result<()> {
+        unreachable!()
+    }
+
+    fn pin(&self) -> Option<u8> {
+        unreachable!()
+    }
+}
+
+impl Interface for Detached {
+    fn read(&mut self, _register: Box<dyn crate::device::registers::Register>) -> Result<u8> {
+        unreachable!()
+    }
+
+    fn write(
+        &mut self,
+        _register: Box<dyn crate::device::registers::Register>,
+        _payload: u8,
+    ) -> Result<()> {
+        unreachable!()
+    }
+}
+
 pub(crate) fn calculate_bitshift(mask: u8) -> u8 {
-    for i in 0..7 {
-        if (mask & (1 << i)) == 1 {
+    for i in 0..8 {
+        if (mask & (1 << i)) != 0 {
             return i;
         }
     }
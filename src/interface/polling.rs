@@ -0,0 +1,129 @@
+use crate::interface::{IrqCallback, IrqSource};
+use rppal::gpio::{InputPin, Level, Trigger};
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+pub const DEFAULT_POLLING_INTERVAL: Duration = Duration::from_millis(100);
+
+type Sample = Box<dyn FnMut() -> Level + Send>;
+
+/// IRQ source for boards whose IRQ line can't raise interrupts, it is checked periodically instead.
+///
+/// The IRQ pin stays high until the interrupt register is read, so no event is missed however long the
+/// interval is; it only delays the events. The levels are sampled rather than the edges counted, so the
+/// oscillator measurement and the antenna tuning need an interrupt-capable pin or an edge counter.
+pub struct PolledIrq {
+    /// Samples the IRQ line, when unset the interrupt register is read directly instead.
+    sample: Option<Arc<Mutex<Sample>>>,
+    pin: Option<u8>,
+    interval: Duration,
+    running: Option<(Sender<()>, JoinHandle<()>)>,
+}
+
+impl PolledIrq {
+    /// Samples the level of the IRQ pin, e.g. one without edge detection.
+    pub fn pin(pin: InputPin) -> Self {
+        let number = pin.pin();
+        let mut polled = Self::level(move || pin.read());
+        polled.pin = Some(number);
+        polled
+    }
+
+    /// Samples the IRQ line with `sample`, e.g. through an I/O expander.
+    pub fn level<F: FnMut() -> Level + Send + 'static>(sample: F) -> Self {
+        Self {
+            sample: Some(Arc::new(Mutex::new(Box::new(sample)))),
+            pin: None,
+            interval: DEFAULT_POLLING_INTERVAL,
+            running: None,
+        }
+    }
+
+    /// Reads the interrupt register directly, for boards without the IRQ line wired.
+    pub fn register() -> Self {
+        Self {
+            sample: None,
+            pin: None,
+            interval: DEFAULT_POLLING_INTERVAL,
+            running: None,
+        }
+    }
+
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+}
+
+impl IrqSource for PolledIrq {
+    fn set_async_interrupt(
+        &mut self,
+        trigger: Trigger,
+        mut callback: IrqCallback,
+    ) -> crate::Result<()> {
+        self.clear_async_interrupt()?;
+
+        let sample = self.sample.clone();
+        let interval = self.interval;
+        let (stop, stopped) = channel::<()>();
+
+        let thread = thread::spawn(move || {
+            let mut previous = None;
+
+            loop {
+                match stopped.recv_timeout(interval) {
+                    Err(RecvTimeoutError::Timeout) => {}
+                    _ => return,
+                }
+
+                let level = match &sample {
+                    Some(sample) => (sample.lock().unwrap())(),
+                    None => {
+                        // the handler waits for the interrupt register to be ready and ignores it when it's clear
+                        callback(Level::High);
+                        continue;
+                    }
+                };
+
+                // levels rather than edges, as the line stays high until the interrupt is handled
+                let triggered = match trigger {
+                    Trigger::Disabled => false,
+                    Trigger::RisingEdge => level == Level::High,
+                    Trigger::FallingEdge => level == Level::Low,
+                    Trigger::Both => previous.is_some() && previous != Some(level),
+                };
+                previous = Some(level);
+
+                if triggered {
+                    callback(level);
+                }
+            }
+        });
+
+        self.running = Some((stop, thread));
+
+        Ok(())
+    }
+
+    fn clear_async_interrupt(&mut self) -> crate::Result<()> {
+        if let Some((stop, thread)) = self.running.take() {
+            drop(stop);
+
+            if thread.join().is_err() {
+                error!("IRQ polling thread panicked");
+            }
+        }
+
+        Ok(())
+    }
+
+    fn pin(&self) -> Option<u8> {
+        self.pin
+    }
+
+    fn counts_edges(&self) -> bool {
+        false
+    }
+}
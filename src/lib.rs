@@ -1,28 +1,60 @@
 #[macro_use]
 extern crate log;
 
+use crate::broadcast::{EventFilter, Subscribers};
 use crate::device::registers::{
-    AfeGainBoost, CalibrateOscillators, DisplayTrcoOnIrqPin, DistanceEstimation, Interrupt,
-    MaskDisturber, MinimumNumberOfLightning, NoiseFloorLevel, PowerDown, PresetDefault,
-    WatchdogThreshold,
+    AfeGainBoost, CalibrateOscillators, DisplayTrcoOnIrqPin, DistanceEstimation,
+    InternalTuningCapacitors, Interrupt, MaskDisturber, MinimumNumberOfLightning, NoiseFloorLevel,
+    PowerDown, PresetDefault, Register, SingleLightningEnergyLsb, SingleLightningEnergyMmsb,
+    SingleLightningEnergyMsb, SpikeRejection, SrcoCalibrationDone, SrcoCalibrationNok,
+    TrcoCalibrationDone, TrcoCalibrationNok, WatchdogThreshold,
 };
-use crate::interface::i2c::{I2cAddress, I2cInterface};
+use crate::diagnostics::EdgeCounter;
+use crate::interface::i2c::{I2cAddress, I2cInterface, SharedI2c};
+use crate::interface::polling::PolledIrq;
 use crate::interface::{
-    Interface, Irq, CLOCK_GENERATION_DELAY, IRQ_TRIGGER_TO_READY_DELAY, LIGHTNING_CALCULATION_DELAY,
+    Bus, Detached, Interface, Irq, IrqSource, SharedInterface, CLOCK_GENERATION_DELAY,
+    IRQ_TRIGGER_TO_READY_DELAY, LIGHTNING_CALCULATION_DELAY,
 };
+use crate::metrics::{MeteredInterface, Metrics};
+use crate::queue::{OverflowPolicy, QueueStats};
+use crate::recalibration::Recalibrator;
+use crate::record::Recorder;
+use crate::watchdog::{repair, ConfigWatchdog, WatchdogContext};
 use rppal::gpio::{InputPin, Level, Trigger};
 use rppal::i2c::I2c;
 use rppal::spi::Spi;
+use std::convert::TryFrom;
 use std::error;
 use std::fmt;
+use std::mem;
 use std::result::Result::Err;
-use std::sync::mpsc::{channel, Receiver, Sender};
-use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, Mutex, PoisonError, Weak};
 use std::thread::sleep;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
+pub mod alert;
+pub mod broadcast;
+#[cfg(feature = "config")]
+pub mod config;
 pub(crate) mod device;
+pub mod diagnostics;
+pub mod group;
+pub mod guard;
+pub mod handler;
 pub mod interface;
+pub mod metrics;
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+pub mod power;
+pub mod queue;
+pub mod recalibration;
+pub mod record;
+pub mod retry;
+pub mod survey;
+pub mod watchdog;
 
 pub type IrqPin = InputPin;
 
@@ -30,15 +62,33 @@ pub type IrqPin = InputPin;
 pub enum Error {
     Deadlock,
     InterfaceError(interface::Error),
+    InvalidArgument(&'static str),
     InvalidState,
+    IrqPinError(rppal::gpio::Error),
+    UnknownDistance(u8),
+    UnknownInterrupt(u8),
+    UnknownSignalVerificationThreshold(u8),
 }
 
 pub type Result<T> = ::std::result::Result<T, Error>;
 
 impl error::Error for Error {}
 impl fmt::Display for Error {
-    fn fmt(&self, _f: &mut fmt::Formatter) -> ::std::result::Result<(), fmt::Error> {
-        unimplemented!()
+    fn fmt(&self, f: &mut fmt::Formatter) -> ::std::result::Result<(), fmt::Error> {
+        match self {
+            Error::Deadlock => write!(f, "deadlock while accessing the sensor"),
+            Error::InterfaceError(e) => e.fmt(f),
+            Error::InvalidArgument(reason) => write!(f, "invalid argument: {}", reason),
+            Error::InvalidState => write!(f, "operation is not allowed in the current state"),
+            Error::IrqPinError(e) => e.fmt(f),
+            Error::UnknownDistance(distance) => {
+                write!(f, "unknown distance estimation: {:#08b}", distance)
+            }
+            Error::UnknownInterrupt(irq) => write!(f, "unknown interrupt: {:#06b}", irq),
+            Error::UnknownSignalVerificationThreshold(threshold) => {
+                write!(f, "unknown signal verification threshold: {}", threshold)
+            }
+        }
     }
 }
 
@@ -48,13 +98,68 @@ impl From<crate::interface::Error> for Error {
     }
 }
 
+impl From<rppal::gpio::Error> for Error {
+    fn from(error: rppal::gpio::Error) -> Self {
+        Error::IrqPinError(error)
+    }
+}
+
+/// Named presets of the [`AfeGain`].
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum SensorPlacing {
     Indoor,
     Outdoor,
 }
 
+/// Gain of the analog front-end, larger values amplify the antenna signal more.
+/// Values between the presets suit e.g. semi-sheltered installations.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "u8", into = "u8"))]
+pub struct AfeGain(pub(crate) u8);
+
+impl AfeGain {
+    pub const INDOOR: AfeGain = AfeGain(0b_1_0010);
+    pub const OUTDOOR: AfeGain = AfeGain(0b_0_1110);
+
+    pub fn new(value: u8) -> ::std::result::Result<Self, &'static str> {
+        if value > 31 {
+            return Err("AFE gain must be in range 0-31");
+        }
+
+        Ok(Self(value))
+    }
+
+    /// The preset this gain corresponds to, if any.
+    pub fn placing(self) -> Option<SensorPlacing> {
+        match self {
+            AfeGain::INDOOR => Some(SensorPlacing::Indoor),
+            AfeGain::OUTDOOR => Some(SensorPlacing::Outdoor),
+            _ => None,
+        }
+    }
+}
+
+impl TryFrom<u8> for AfeGain {
+    type Error = &'static str;
+
+    fn try_from(value: u8) -> ::std::result::Result<Self, Self::Error> {
+        Self::new(value)
+    }
+}
+
+impl From<SensorPlacing> for AfeGain {
+    fn from(placing: SensorPlacing) -> Self {
+        match placing {
+            SensorPlacing::Indoor => AfeGain::INDOOR,
+            SensorPlacing::Outdoor => AfeGain::OUTDOOR,
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum MinimumLightningThreshold {
     One,
     Five,
@@ -66,6 +171,8 @@ pub enum MinimumLightningThreshold {
 /// Refer to Figure 20 in the datasheet for the relationship between this threshold and its impact.
 /// Defaults to 2.
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "u8", into = "u8"))]
 pub struct SignalVerificationThreshold(pub(crate) u8);
 
 impl SignalVerificationThreshold {
@@ -78,20 +185,130 @@ impl SignalVerificationThreshold {
     }
 }
 
+impl TryFrom<u8> for SignalVerificationThreshold {
+    type Error = &'static str;
+
+    fn try_from(value: u8) -> ::std::result::Result<Self, Self::Error> {
+        Self::new(value)
+    }
+}
+
+/// Larger values make the sensor reject more spikes, at the cost of the detection efficiency.
+/// Defaults to 2.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "u8", into = "u8"))]
+pub struct SpikeRejectionThreshold(pub(crate) u8);
+
+impl SpikeRejectionThreshold {
+    pub fn new(value: u8) -> ::std::result::Result<Self, &'static str> {
+        if value > 15 {
+            return Err("Spike rejection threshold must be in range 0-15");
+        }
+
+        Ok(Self(value))
+    }
+}
+
+impl TryFrom<u8> for SpikeRejectionThreshold {
+    type Error = &'static str;
+
+    fn try_from(value: u8) -> ::std::result::Result<Self, Self::Error> {
+        Self::new(value)
+    }
+}
+
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "u8", into = "u8"))]
 pub struct NoiseFloorThreshold(pub(crate) u8);
 
+/// Continuous input noise level of each NF_LEV setting in µVrms, outdoor and indoor, per Table 16 of the datasheet.
+const NOISE_FLOOR_LEVELS: [(u16, u16); 8] = [
+    (390, 28),
+    (630, 45),
+    (860, 62),
+    (1100, 78),
+    (1140, 95),
+    (1570, 112),
+    (1800, 130),
+    (2000, 146),
+];
+
 impl NoiseFloorThreshold {
     pub fn new(value: u8) -> ::std::result::Result<Self, &'static str> {
-        if value > 11 {
-            return Err("Noise level threshold must be in range 0-11");
+        if value > 7 {
+            return Err("Noise level threshold must be in range 0-7");
+        }
+
+        Ok(Self(value))
+    }
+
+    /// The setting whose noise level is nearest to `microvolts` µVrms with the AFE gain of `placing`.
+    pub fn from_microvolts(placing: SensorPlacing, microvolts: u16) -> Self {
+        let nearest = (0..NOISE_FLOOR_LEVELS.len())
+            .min_by_key(|&setting| {
+                let level = i32::from(Self(setting as u8).microvolts(placing));
+                (level - i32::from(microvolts)).abs()
+            })
+            .unwrap_or_default();
+
+        Self(nearest as u8)
+    }
+
+    /// Noise level in µVrms above which the sensor reports noise, with the AFE gain of `placing`.
+    pub fn microvolts(self, placing: SensorPlacing) -> u16 {
+        let (outdoor, indoor) = NOISE_FLOOR_LEVELS[usize::from(self.0)];
+
+        match placing {
+            SensorPlacing::Indoor => indoor,
+            SensorPlacing::Outdoor => outdoor,
+        }
+    }
+}
+
+impl TryFrom<u8> for NoiseFloorThreshold {
+    type Error = &'static str;
+
+    fn try_from(value: u8) -> ::std::result::Result<Self, Self::Error> {
+        Self::new(value)
+    }
+}
+
+/// Internal tuning capacitors of the antenna's LC circuit, in steps of 8 pF (0-120 pF).
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "u8", into = "u8"))]
+pub struct TuningCapacitors(pub(crate) u8);
+
+impl TuningCapacitors {
+    pub fn new(value: u8) -> ::std::result::Result<Self, &'static str> {
+        if value > 15 {
+            return Err("Tuning capacitors must be in range 0-15");
         }
 
         Ok(Self(value))
     }
+
+    pub fn value(&self) -> u8 {
+        self.0
+    }
+
+    pub fn picofarads(&self) -> u8 {
+        self.0 * 8
+    }
+}
+
+impl TryFrom<u8> for TuningCapacitors {
+    type Error = &'static str;
+
+    fn try_from(value: u8) -> ::std::result::Result<Self, Self::Error> {
+        Self::new(value)
+    }
 }
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum IgnoreDisturbances {
     Yes,
     No,
@@ -99,6 +316,7 @@ pub enum IgnoreDisturbances {
 
 /// Estimated distance to the head of storm, in kilometers.
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum HeadOfStormDistance {
     /// the storm is within 5-40 km range
     Kilometers(u8),
@@ -108,36 +326,76 @@ pub enum HeadOfStormDistance {
     Overhead,
 }
 
+/// Energy of a single lightning, a pure number without physical meaning (20 bits).
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LightningEnergy(pub(crate) u32);
+
+impl LightningEnergy {
+    pub fn value(&self) -> u32 {
+        self.0
+    }
+}
+
 pub enum InterfaceSelection {
     I2c(I2c, I2cAddress),
+    /// I²C bus which other drivers use too.
+    SharedI2c(SharedI2c, I2cAddress),
     Spi(Spi, u8),
 }
 
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Event {
     Disturbance,
-    Lightning(HeadOfStormDistance),
+    Lightning(HeadOfStormDistance, LightningEnergy),
     Noise,
+    /// Reported by the driver itself rather than by the sensor's IRQ.
+    Status(Status),
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Status {
+    /// The sensor lost its configuration, e.g. due to a brownout, and it was re-applied.
+    SensorReset,
+    /// The sensor does not respond on the bus, even after retrying and re-applying its configuration.
+    SensorUnavailable,
+    /// The RC oscillators were recalibrated while listening.
+    Recalibrated(CalibrationStatus),
 }
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 enum State {
     Listening,
+    /// Put to sleep while listening, the events channel stays open until the sensor is woken up.
+    Paused,
     PoweredDown,
+    /// Put to sleep while standing by, calibrated and configured.
+    Sleeping,
     StandingBy,
 }
 
-#[derive(Default)]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ListeningParameters {
-    pub(crate) sensor_placing: Option<SensorPlacing>,
+    pub(crate) afe_gain: Option<AfeGain>,
     pub(crate) minimum_lightning_threshold: Option<MinimumLightningThreshold>,
     pub(crate) noise_floor_threshold: Option<NoiseFloorThreshold>,
     pub(crate) signal_verification_threshold: Option<SignalVerificationThreshold>,
+    pub(crate) spike_rejection_threshold: Option<SpikeRejectionThreshold>,
     pub(crate) ignore_disturbances: Option<IgnoreDisturbances>,
+    pub(crate) tuning_capacitors: Option<TuningCapacitors>,
 }
 
 impl ListeningParameters {
-    pub fn with_sensor_placing(mut self, sensor_placing: SensorPlacing) -> Self {
-        self.sensor_placing = Some(sensor_placing);
+    /// Sets the AFE gain to the preset of `sensor_placing`.
+    pub fn with_sensor_placing(self, sensor_placing: SensorPlacing) -> Self {
+        self.with_afe_gain(sensor_placing.into())
+    }
+
+    pub fn with_afe_gain(mut self, afe_gain: AfeGain) -> Self {
+        self.afe_gain = Some(afe_gain);
         self
     }
 
@@ -165,32 +423,186 @@ impl ListeningParameters {
         self
     }
 
+    pub fn with_spike_rejection_threshold(
+        mut self,
+        spike_rejection_threshold: SpikeRejectionThreshold,
+    ) -> Self {
+        self.spike_rejection_threshold = Some(spike_rejection_threshold);
+        self
+    }
+
     pub fn with_ignore_disturbances(mut self, ignore_disturbances: IgnoreDisturbances) -> Self {
         self.ignore_disturbances = Some(ignore_disturbances);
         self
     }
+
+    pub fn with_tuning_capacitors(mut self, tuning_capacitors: TuningCapacitors) -> Self {
+        self.tuning_capacitors = Some(tuning_capacitors);
+        self
+    }
+
+    /// Noise level in µVrms of the noise floor threshold, known only with an AFE gain preset.
+    pub fn noise_floor_microvolts(&self) -> Option<u16> {
+        let placing = self.afe_gain.and_then(AfeGain::placing)?;
+
+        self.noise_floor_threshold
+            .map(|noise_floor_threshold| noise_floor_threshold.microvolts(placing))
+    }
+
+    /// Parameters set in `other` take precedence over the ones set in `self`.
+    pub(crate) fn merge(self, other: ListeningParameters) -> Self {
+        Self {
+            afe_gain: other.afe_gain.or(self.afe_gain),
+            minimum_lightning_threshold: other
+                .minimum_lightning_threshold
+                .or(self.minimum_lightning_threshold),
+            noise_floor_threshold: other.noise_floor_threshold.or(self.noise_floor_threshold),
+            signal_verification_threshold: other
+                .signal_verification_threshold
+                .or(self.signal_verification_threshold),
+            spike_rejection_threshold: other
+                .spike_rejection_threshold
+                .or(self.spike_rejection_threshold),
+            ignore_disturbances: other.ignore_disturbances.or(self.ignore_disturbances),
+            tuning_capacitors: other.tuning_capacitors.or(self.tuning_capacitors),
+        }
+    }
+}
+
+/// Result of the RC oscillators calibration.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CalibrationStatus {
+    /// TRCO (32.768 kHz timer RC oscillator) was calibrated successfully.
+    pub trco: bool,
+    /// SRCO (1.1 MHz system RC oscillator) was calibrated successfully.
+    pub srco: bool,
+}
+
+impl CalibrationStatus {
+    pub fn is_successful(&self) -> bool {
+        self.trco && self.srco
+    }
 }
 
 pub struct AS3935 {
-    interface: Arc<Mutex<Box<dyn Interface>>>,
-    irq_pin: IrqPin,
+    interface: Arc<SharedInterface>,
+    bus: Bus,
+    irq_pin: Box<dyn IrqSource>,
+    irq_mask: Arc<IrqMask>,
+    /// Shared with the IRQ handler and the watchdog, which re-apply them when the sensor lost them.
+    parameters: Arc<Mutex<ListeningParameters>>,
     state: State,
+    metrics: Option<Arc<Metrics>>,
+    recorder: Option<Arc<Recorder>>,
+    watchdog: Option<ConfigWatchdog>,
+    recalibrator: Option<Recalibrator>,
+    /// Whether this instance powered the sensor up, only then it's powered down when dropped.
+    powered_up: bool,
+    /// Counts the IRQ pin edges for the oscillator measurements instead of the IRQ source.
+    edge_counter: Option<Box<dyn EdgeCounter>>,
+    event_queue: Option<(usize, OverflowPolicy)>,
+    queue_stats: Arc<QueueStats>,
+    /// Owned by the IRQ handler while listening, so that the subscriptions end with it.
+    subscribers: Weak<Subscribers>,
 }
 
 impl AS3935 {
     pub fn new(interface_selection: InterfaceSelection, irq_pin: IrqPin) -> Result<Self> {
-        Ok(match interface_selection {
-            InterfaceSelection::I2c(i2c, i2c_address) => Self {
-                interface: Arc::new(Mutex::new(Box::new(I2cInterface::new(i2c, i2c_address)?))),
-                irq_pin,
-                state: State::StandingBy,
-            },
+        Self::connect(interface_selection, Box::new(irq_pin))
+    }
+
+    /// Checks the IRQ line, or the interrupt register, periodically instead of waiting for interrupts.
+    pub fn new_polling(interface_selection: InterfaceSelection, irq: PolledIrq) -> Result<Self> {
+        Self::connect(interface_selection, Box::new(irq))
+    }
+
+    fn connect(
+        interface_selection: InterfaceSelection,
+        irq_source: Box<dyn IrqSource>,
+    ) -> Result<Self> {
+        let (i2c, i2c_address) = match interface_selection {
+            InterfaceSelection::I2c(i2c, i2c_address) => (Arc::new(Mutex::new(i2c)), i2c_address),
+            InterfaceSelection::SharedI2c(i2c, i2c_address) => (i2c, i2c_address),
             InterfaceSelection::Spi(_, _) => unimplemented!(),
-        })
+        };
+        let bus = i2c.lock().unwrap_or_else(PoisonError::into_inner).bus();
+
+        Ok(Self::from_parts(
+            Bus::I2c {
+                bus,
+                address: i2c_address.0,
+            },
+            Box::new(I2cInterface::new(i2c, i2c_address)),
+            irq_source,
+        ))
+    }
+
+    pub(crate) fn from_parts(
+        bus: Bus,
+        interface: Box<dyn Interface>,
+        irq_pin: Box<dyn IrqSource>,
+    ) -> Self {
+        Self {
+            bus,
+            interface: Arc::new(SharedInterface::new(interface)),
+            irq_pin,
+            irq_mask: Arc::new(IrqMask::default()),
+            parameters: Arc::new(Mutex::new(ListeningParameters::default())),
+            state: State::StandingBy,
+            metrics: None,
+            recorder: None,
+            watchdog: None,
+            recalibrator: None,
+            powered_up: false,
+            edge_counter: None,
+            event_queue: None,
+            queue_stats: Arc::new(QueueStats::default()),
+            subscribers: Weak::new(),
+        }
+    }
+
+    /// Keeps the metrics up to date with the events, the configuration and the bus errors of the sensor.
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        let metered = metrics.clone();
+        self.decorate_interface(move |interface| {
+            Box::new(MeteredInterface::new(interface, metered))
+        });
+
+        metrics.set_listening(self.is_listening());
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Makes accesses to the sensor fail with [`Error::Deadlock`] instead of waiting longer than `timeout` for
+    /// another one, e.g. by the IRQ handler, to finish. Defaults to
+    /// [`DEFAULT_LOCK_TIMEOUT`](interface::DEFAULT_LOCK_TIMEOUT).
+    pub fn with_lock_timeout(self, timeout: Duration) -> Self {
+        self.interface.set_timeout(timeout);
+        self
+    }
+
+    /// Bounds the events channel of [`listen`](AS3935::listen) to `capacity` events, applying `policy` to the
+    /// events which arrive while it is full. The channel is unbounded by default.
+    ///
+    /// Besides the `capacity` queued events, the channel holds the one being handed over to the receiver.
+    pub fn with_event_queue(mut self, capacity: usize, policy: OverflowPolicy) -> Self {
+        self.event_queue = Some((capacity.max(1), policy));
+        self
+    }
+
+    /// Counters of the events dropped by the bounded queue of the [`listen`](AS3935::listen) channel, they keep
+    /// counting across listens. Subscriptions have their own, see
+    /// [`subscribe_with_stats`](AS3935::subscribe_with_stats).
+    pub fn queue_stats(&self) -> Arc<QueueStats> {
+        self.queue_stats.clone()
     }
 
     pub fn listen(&mut self, parameters: ListeningParameters) -> Result<Receiver<Event>> {
-        self.assert_state(&self.state, &[State::StandingBy, State::PoweredDown])?;
+        self.assert_state(
+            &self.state,
+            &[State::StandingBy, State::PoweredDown, State::Sleeping],
+        )?;
 
         info!("starting listen sequence");
 
@@ -205,19 +617,41 @@ impl AS3935 {
 
         debug!("configuring listen parameters");
         self.configure_listen_parameters(parameters)?;
-
-        let (sender, receiver) = channel::<Event>();
-        self.setup_irq(sender)?;
+        *self
+            .parameters
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = parameters;
+
+        let (sender, receiver) = queue::channel(self.event_queue, self.queue_stats.clone());
+        let subscribers = Arc::new(Subscribers::default());
+        subscribers.add(EventFilter::default(), sender);
+        self.subscribers = Arc::downgrade(&subscribers);
+        self.setup_irq(subscribers.clone())?;
+
+        let context = WatchdogContext {
+            interface: self.interface.clone(),
+            irq_mask: self.irq_mask.clone(),
+            metrics: self.metrics.clone(),
+            parameters: self.parameters.clone(),
+            subscribers,
+        };
+        if let Some(recalibrator) = &mut self.recalibrator {
+            recalibrator.start(context.clone());
+        }
+        if let Some(watchdog) = &mut self.watchdog {
+            watchdog.start(context);
+        }
 
         self.state = State::Listening;
+        if let Some(metrics) = &self.metrics {
+            metrics.set_listening(true);
+        }
 
         Ok(receiver)
     }
 
     pub fn terminate(&mut self) -> Result<()> {
-        self.assert_state(&self.state, &[State::Listening])?;
-
-        self.irq_pin.clear_async_interrupt().unwrap();
+        self.stop_listening()?;
         self.power_down()?;
 
         self.state = State::PoweredDown;
@@ -225,72 +659,219 @@ impl AS3935 {
         Ok(())
     }
 
-    pub fn is_listening(&self) -> bool {
-        self.state == State::Listening
-    }
+    /// Powers the sensor down, keeping its configuration. A listening sensor keeps its events channel open and
+    /// resumes listening once woken up.
+    pub fn sleep(&mut self) -> Result<()> {
+        self.assert_state(&self.state, &[State::Listening, State::StandingBy])?;
 
-    fn power_up(&mut self) -> Result<()> {
-        self.assert_state(&self.state, &[State::StandingBy, State::PoweredDown])?;
+        debug!("powering down to sleep");
+        if let Some(watchdog) = &self.watchdog {
+            watchdog.set_active(false);
+        }
+        if let Some(recalibrator) = &self.recalibrator {
+            recalibrator.set_active(false);
+        }
+        self.power_down()?;
 
-        self.interface
-            .lock()
-            .unwrap()
-            .write(Box::new(PowerDown), 0b_0)?;
-        sleep(Duration::from_millis(2));
+        self.state = match self.state {
+            State::Listening => State::Paused,
+            _ => State::Sleeping,
+        };
+        if let Some(metrics) = &self.metrics {
+            metrics.set_listening(false);
+        }
 
         Ok(())
     }
 
-    fn power_down(&mut self) -> Result<()> {
-        self.interface
+    /// Powers the sensor up and recalibrates its RC oscillators, as required after leaving power-down.
+    pub fn wake(&mut self) -> Result<CalibrationStatus> {
+        self.assert_state(&self.state, &[State::Paused, State::Sleeping])?;
+
+        debug!("waking up");
+        self.power_up()?;
+        self.calibrate_clock()?;
+
+        self.state = match self.state {
+            State::Paused => State::Listening,
+            _ => State::StandingBy,
+        };
+        if let Some(metrics) = &self.metrics {
+            metrics.set_listening(self.is_listening());
+        }
+        if let Some(watchdog) = &self.watchdog {
+            watchdog.set_active(true);
+        }
+        if let Some(recalibrator) = &self.recalibrator {
+            recalibrator.set_active(true);
+        }
+
+        self.calibration_status()
+    }
+
+    /// Applies the parameters to the sensor right away, parameters which are not set are left untouched.
+    pub fn configure(&mut self, parameters: ListeningParameters) -> Result<()> {
+        self.configure_listen_parameters(parameters)?;
+
+        let mut current = self
+            .parameters
             .lock()
-            .unwrap()
-            .write(Box::new(PowerDown), 0b_1)?;
+            .unwrap_or_else(PoisonError::into_inner);
+        *current = current.merge(parameters);
 
         Ok(())
     }
 
-    fn calibrate_clock(&mut self) -> Result<()> {
-        self.assert_state(&self.state, &[State::StandingBy, State::PoweredDown])?;
+    /// Powers the sensor up if needed and calibrates its RC oscillators.
+    pub fn calibrate(&mut self) -> Result<CalibrationStatus> {
+        self.assert_state(
+            &self.state,
+            &[State::StandingBy, State::PoweredDown, State::Sleeping],
+        )?;
 
-        debug!("sending CALIB_RCO direct command");
-        self.interface
-            .lock()
-            .unwrap()
-            .write(Box::new(CalibrateOscillators), 0x96)?;
-        sleep(Duration::from_millis(2));
+        self.power_up()?;
+        self.state = State::StandingBy;
+        self.calibrate_clock()?;
 
-        debug!("setting DISP_TRCO=1");
-        self.interface
-            .lock()
-            .unwrap()
-            .write(Box::new(DisplayTrcoOnIrqPin), 0b_1)?;
+        self.calibration_status()
+    }
 
-        sleep(CLOCK_GENERATION_DELAY);
+    pub fn calibration_status(&mut self) -> Result<CalibrationStatus> {
+        read_calibration_status(&self.interface)
+    }
 
-        debug!("setting DISP_TRCO=0");
-        self.interface
+    pub fn is_listening(&self) -> bool {
+        self.state == State::Listening
+    }
+
+    /// The sensor was put to sleep and has to be woken up with [`wake`](AS3935::wake).
+    pub fn is_sleeping(&self) -> bool {
+        self.state == State::Paused || self.state == State::Sleeping
+    }
+
+    /// Bus the sensor is connected to.
+    pub fn bus(&self) -> Bus {
+        self.bus
+    }
+
+    /// Parameters of the last [`listen`](AS3935::listen) call.
+    pub fn parameters(&self) -> ListeningParameters {
+        *self
+            .parameters
             .lock()
-            .unwrap()
-            .write(Box::new(DisplayTrcoOnIrqPin), 0)?;
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Reads the configuration back from the sensor, every parameter is set.
+    ///
+    /// The watchdog threshold register holds 4 bits but only 0-10 are defined, anything above fails with
+    /// [`Error::UnknownSignalVerificationThreshold`].
+    pub fn current_parameters(&mut self) -> Result<ListeningParameters> {
+        let mut interface = self.interface.lock()?;
+        let watchdog_threshold = interface.read(Box::new(WatchdogThreshold))?;
+
+        Ok(ListeningParameters {
+            afe_gain: Some(AfeGain(interface.read(Box::new(AfeGainBoost))?)),
+            minimum_lightning_threshold: Some(MinimumLightningThreshold::from(
+                interface.read(Box::new(MinimumNumberOfLightning))?,
+            )),
+            noise_floor_threshold: Some(NoiseFloorThreshold(
+                interface.read(Box::new(NoiseFloorLevel))?,
+            )),
+            signal_verification_threshold: Some(
+                SignalVerificationThreshold::new(watchdog_threshold)
+                    .map_err(|_| Error::UnknownSignalVerificationThreshold(watchdog_threshold))?,
+            ),
+            spike_rejection_threshold: Some(SpikeRejectionThreshold(
+                interface.read(Box::new(SpikeRejection))?,
+            )),
+            ignore_disturbances: Some(IgnoreDisturbances::from(
+                interface.read(Box::new(MaskDisturber))?,
+            )),
+            tuning_capacitors: Some(TuningCapacitors(
+                interface.read(Box::new(InternalTuningCapacitors))?,
+            )),
+        })
+    }
+
+    /// Stops the IRQ handling, a listening sensor is left standing by and a paused one sleeping.
+    fn stop_listening(&mut self) -> Result<()> {
+        self.assert_state(&self.state, &[State::Listening, State::Paused])?;
+
+        if let Some(watchdog) = &mut self.watchdog {
+            watchdog.stop();
+        }
+        if let Some(recalibrator) = &mut self.recalibrator {
+            recalibrator.stop();
+        }
+        self.irq_pin.clear_async_interrupt()?;
+
+        self.state = match self.state {
+            State::Paused => State::Sleeping,
+            _ => State::StandingBy,
+        };
+        if let Some(metrics) = &self.metrics {
+            metrics.set_listening(false);
+        }
+
+        Ok(())
+    }
+
+    fn power_up(&mut self) -> Result<()> {
+        self.assert_state(
+            &self.state,
+            &[
+                State::StandingBy,
+                State::PoweredDown,
+                State::Sleeping,
+                State::Paused,
+            ],
+        )?;
+
+        self.interface.lock()?.write(Box::new(PowerDown), 0b_0)?;
         sleep(Duration::from_millis(2));
+        self.powered_up = true;
 
         Ok(())
     }
 
+    fn power_down(&mut self) -> Result<()> {
+        self.interface.lock()?.write(Box::new(PowerDown), 0b_1)?;
+
+        Ok(())
+    }
+
+    fn calibrate_clock(&mut self) -> Result<()> {
+        self.assert_state(
+            &self.state,
+            &[
+                State::StandingBy,
+                State::PoweredDown,
+                State::Sleeping,
+                State::Paused,
+            ],
+        )?;
+
+        calibrate_oscillators(&self.interface, &self.irq_mask)
+    }
+
     fn configure_defaults(&mut self) -> Result<()> {
         self.interface
-            .lock()
-            .unwrap()
+            .lock()?
             .write(Box::new(PresetDefault), 0x96)?;
 
+        if let Some(metrics) = &self.metrics {
+            metrics.set_noise_floor_threshold(NoiseFloorLevel.default_value());
+            metrics.set_signal_verification_threshold(WatchdogThreshold.default_value());
+        }
+
         Ok(())
     }
 
     fn configure_listen_parameters(&mut self, parameters: ListeningParameters) -> Result<()> {
-        if let Some(sensor_placing) = parameters.sensor_placing {
-            debug!("configuring sensor placing");
-            self.configure_sensor_placing(&sensor_placing)?;
+        if let Some(afe_gain) = &parameters.afe_gain {
+            debug!("configuring AFE gain");
+            self.configure_afe_gain(afe_gain)?;
         }
 
         if let Some(minimum_lightning_threshold) = &parameters.minimum_lightning_threshold {
@@ -299,7 +880,12 @@ impl AS3935 {
         }
 
         if let Some(noise_floor_threshold) = &parameters.noise_floor_threshold {
-            debug!("configuring noise floor threshold");
+            match self.parameters().merge(parameters).noise_floor_microvolts() {
+                Some(microvolts) => {
+                    debug!("configuring noise floor threshold ({} µVrms)", microvolts)
+                }
+                None => debug!("configuring noise floor threshold"),
+            }
             self.configure_noise_floor_threshold(&noise_floor_threshold)?;
         }
 
@@ -308,19 +894,28 @@ impl AS3935 {
             self.configure_signal_verification_threshold(&signal_verification_threshold)?;
         }
 
+        if let Some(spike_rejection_threshold) = &parameters.spike_rejection_threshold {
+            debug!("configuring spike rejection threshold");
+            self.configure_spike_rejection_threshold(spike_rejection_threshold)?;
+        }
+
         if let Some(ignore_disturbances) = &parameters.ignore_disturbances {
             debug!("configuring ignoring of disturbances");
             self.configure_ignore_disturbances(&ignore_disturbances)?;
         }
 
+        if let Some(tuning_capacitors) = &parameters.tuning_capacitors {
+            debug!("configuring tuning capacitors");
+            self.configure_tuning_capacitors(tuning_capacitors)?;
+        }
+
         Ok(())
     }
 
-    fn configure_sensor_placing(&mut self, placing: &SensorPlacing) -> Result<()> {
+    fn configure_afe_gain(&mut self, afe_gain: &AfeGain) -> Result<()> {
         self.interface
-            .lock()
-            .unwrap()
-            .write(Box::new(AfeGainBoost), (*placing).into())?;
+            .lock()?
+            .write(Box::new(AfeGainBoost), (*afe_gain).into())?;
 
         Ok(())
     }
@@ -329,7 +924,7 @@ impl AS3935 {
         &mut self,
         minimum_lightning_threshold: &MinimumLightningThreshold,
     ) -> Result<()> {
-        self.interface.lock().unwrap().write(
+        self.interface.lock()?.write(
             Box::new(MinimumNumberOfLightning),
             (*minimum_lightning_threshold).into(),
         )?;
@@ -342,10 +937,13 @@ impl AS3935 {
         noise_floor_threshold: &NoiseFloorThreshold,
     ) -> Result<()> {
         self.interface
-            .lock()
-            .unwrap()
+            .lock()?
             .write(Box::new(NoiseFloorLevel), (*noise_floor_threshold).into())?;
 
+        if let Some(metrics) = &self.metrics {
+            metrics.set_noise_floor_threshold(noise_floor_threshold.0);
+        }
+
         Ok(())
     }
 
@@ -353,11 +951,27 @@ impl AS3935 {
         &mut self,
         signal_verification_threshold: &SignalVerificationThreshold,
     ) -> Result<()> {
-        self.interface.lock().unwrap().write(
+        self.interface.lock()?.write(
             Box::new(WatchdogThreshold),
             (*signal_verification_threshold).into(),
         )?;
 
+        if let Some(metrics) = &self.metrics {
+            metrics.set_signal_verification_threshold(signal_verification_threshold.0);
+        }
+
+        Ok(())
+    }
+
+    fn configure_spike_rejection_threshold(
+        &mut self,
+        spike_rejection_threshold: &SpikeRejectionThreshold,
+    ) -> Result<()> {
+        self.interface.lock()?.write(
+            Box::new(SpikeRejection),
+            (*spike_rejection_threshold).into(),
+        )?;
+
         Ok(())
     }
 
@@ -366,41 +980,55 @@ impl AS3935 {
         ignore_disturbances: &IgnoreDisturbances,
     ) -> Result<()> {
         self.interface
-            .lock()
-            .unwrap()
+            .lock()?
             .write(Box::new(MaskDisturber), (*ignore_disturbances).into())?;
 
         Ok(())
     }
 
-    fn setup_irq(&mut self, sender: Sender<Event>) -> Result<()> {
-        let interface_mutex = self.interface.clone();
+    fn configure_tuning_capacitors(&mut self, tuning_capacitors: &TuningCapacitors) -> Result<()> {
+        self.interface.lock()?.write(
+            Box::new(InternalTuningCapacitors),
+            (*tuning_capacitors).into(),
+        )?;
 
-        self.irq_pin
-            .set_async_interrupt(Trigger::RisingEdge, move |_level: Level| {
-                sleep(IRQ_TRIGGER_TO_READY_DELAY);
+        Ok(())
+    }
 
-                let mut interface = interface_mutex.lock().unwrap();
+    fn setup_irq(&mut self, subscribers: Arc<Subscribers>) -> Result<()> {
+        let handler = Arc::new(IrqHandler {
+            interface: self.interface.clone(),
+            irq_mask: self.irq_mask.clone(),
+            parameters: self.parameters.clone(),
+            metrics: self.metrics.clone(),
+            recorder: self.recorder.clone(),
+            subscribers,
+            recovering: AtomicBool::new(false),
+        });
+        self.irq_mask.set_handler(&handler);
+
+        self.irq_pin.set_async_interrupt(
+            Trigger::RisingEdge,
+            Box::new(move |_level: Level| handler.handle()),
+        )?;
 
-                let irq = Irq::from(interface.read(Box::new(Interrupt)).unwrap());
+        Ok(())
+    }
 
-                let event = match irq {
-                    Irq::DistanceEstimationChanged => return,
-                    Irq::DisturberDetected => Event::Disturbance,
-                    Irq::Lightning => {
-                        sleep(LIGHTNING_CALCULATION_DELAY);
-                        Event::Lightning(HeadOfStormDistance::from(
-                            interface.read(Box::new(DistanceEstimation)).unwrap(),
-                        ))
-                    }
-                    Irq::NoiseLevelTooHigh => Event::Noise,
-                };
+    fn decorate_interface<F>(&mut self, decorate: F)
+    where
+        F: FnOnce(Box<dyn Interface>) -> Box<dyn Interface>,
+    {
+        self.interface.decorate(decorate);
+    }
 
-                sender.send(event).unwrap();
-            })
-            .unwrap();
+    fn decorate_irq_source<F>(&mut self, decorate: F)
+    where
+        F: FnOnce(Box<dyn IrqSource>) -> Box<dyn IrqSource>,
+    {
+        let irq_pin = mem::replace(&mut self.irq_pin, Box::new(Detached));
 
-        Ok(())
+        self.irq_pin = decorate(irq_pin);
     }
 
     fn assert_state(&self, state: &State, valid_states: &[State]) -> Result<()> {
@@ -411,3 +1039,240 @@ impl AS3935 {
         Ok(())
     }
 }
+
+impl Drop for AS3935 {
+    /// Terminates the listening and powers the sensor down, on a best-effort basis.
+    ///
+    /// A sensor this instance did not power up is left alone, e.g. as another process may be listening on it.
+    fn drop(&mut self) {
+        let result = match self.state {
+            State::Listening | State::Paused => self.terminate(),
+            State::StandingBy if self.powered_up => self.power_down(),
+            State::StandingBy | State::PoweredDown | State::Sleeping => Ok(()),
+        };
+
+        if let Err(e) = result {
+            warn!("failed to power the sensor down: {}", e);
+        }
+    }
+}
+
+/// Makes the IRQ handler ignore the IRQ pin while it displays an oscillator.
+#[derive(Default)]
+pub(crate) struct IrqMask {
+    masked: AtomicBool,
+    /// Set while listening. The sensor keeps the IRQ pin high until the interrupt register is read, so an
+    /// interrupt raised while masked makes no further edge and has to be read out once unmasked.
+    handler: Mutex<Weak<IrqHandler>>,
+}
+
+impl IrqMask {
+    fn is_masked(&self) -> bool {
+        self.masked.load(Ordering::SeqCst)
+    }
+
+    fn set_handler(&self, handler: &Arc<IrqHandler>) {
+        *self.handler.lock().unwrap_or_else(PoisonError::into_inner) = Arc::downgrade(handler);
+    }
+
+    /// Lets the IRQ handler read out an interrupt raised while masked.
+    fn handle_pending(&self) {
+        let handler = self
+            .handler
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .upgrade();
+
+        if let Some(handler) = handler {
+            handler.handle();
+        }
+    }
+}
+
+/// Reads out and publishes the events when the sensor raises the IRQ.
+struct IrqHandler {
+    interface: Arc<SharedInterface>,
+    irq_mask: Arc<IrqMask>,
+    parameters: Arc<Mutex<ListeningParameters>>,
+    metrics: Option<Arc<Metrics>>,
+    recorder: Option<Arc<Recorder>>,
+    subscribers: Arc<Subscribers>,
+    /// The recovery calibrates the oscillators and reads the interrupt register afterwards anyway.
+    recovering: AtomicBool,
+}
+
+impl IrqHandler {
+    fn handle(&self) {
+        if self.irq_mask.is_masked() || self.recovering.load(Ordering::SeqCst) {
+            return;
+        }
+
+        let triggered_at = Instant::now();
+        sleep(IRQ_TRIGGER_TO_READY_DELAY);
+
+        let result = self.read_event().or_else(|e| {
+            error!("failed to read the event, recovering the sensor: {}", e);
+            self.recover()
+        });
+
+        let event = match result {
+            Ok(Some(event)) => event,
+            Ok(None) => return,
+            Err(e) => {
+                error!("failed to recover the sensor: {}", e);
+
+                if let Some(metrics) = &self.metrics {
+                    metrics.observe_status(&Status::SensorUnavailable);
+                }
+                self.subscribers
+                    .publish(Event::Status(Status::SensorUnavailable));
+
+                return;
+            }
+        };
+
+        if let Some(metrics) = &self.metrics {
+            metrics.observe_event(&event, triggered_at.elapsed());
+        }
+
+        if let Some(recorder) = &self.recorder {
+            recorder.record_event(&event);
+        }
+
+        self.subscribers.publish(event);
+    }
+
+    fn read_event(&self) -> Result<Option<Event>> {
+        read_event(&mut **self.interface.lock()?)
+    }
+
+    /// Re-applies the configuration and reads the interrupt register again, as the sensor holds the IRQ pin high
+    /// until it is read, so the handler would not be called again otherwise.
+    fn recover(&self) -> Result<Option<Event>> {
+        let parameters = *self
+            .parameters
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        self.recovering.store(true, Ordering::SeqCst);
+        let result = repair(&self.interface, &self.irq_mask, &parameters, true);
+        self.recovering.store(false, Ordering::SeqCst);
+        result?;
+
+        self.read_event()
+    }
+}
+
+/// Reads out the interrupt which raised the IRQ, `None` if it does not make an event.
+///
+/// An interrupt the sensor does not define, e.g. read from a sensor which lost its power, fails with
+/// [`Error::UnknownInterrupt`], likewise an undefined distance estimation with [`Error::UnknownDistance`].
+fn read_event(interface: &mut dyn Interface) -> Result<Option<Event>> {
+    let irq =
+        Irq::try_from(interface.read(Box::new(Interrupt))?).map_err(Error::UnknownInterrupt)?;
+
+    Ok(Some(match irq {
+        Irq::DistanceEstimationChanged => return Ok(None),
+        Irq::DisturberDetected => Event::Disturbance,
+        Irq::Lightning => {
+            sleep(LIGHTNING_CALCULATION_DELAY);
+            let mmsb = interface.read(Box::new(SingleLightningEnergyMmsb))?;
+            let msb = interface.read(Box::new(SingleLightningEnergyMsb))?;
+            let lsb = interface.read(Box::new(SingleLightningEnergyLsb))?;
+
+            Event::Lightning(
+                HeadOfStormDistance::try_from(interface.read(Box::new(DistanceEstimation))?)
+                    .map_err(Error::UnknownDistance)?,
+                LightningEnergy::from([mmsb, msb, lsb]),
+            )
+        }
+        Irq::NoiseLevelTooHigh => Event::Noise,
+    }))
+}
+
+/// Calibrates the RC oscillators, meanwhile the IRQ handler ignores the IRQ pin as it displays TRCO.
+///
+/// Calibrations started by the IRQ handler, the watchdog and the recalibration run one at a time, so that the
+/// mask is cleared only once TRCO is no longer displayed. An interrupt raised meanwhile is read out afterwards.
+pub(crate) fn calibrate_oscillators(interface: &SharedInterface, irq_mask: &IrqMask) -> Result<()> {
+    let result = {
+        let _calibration = interface.lock_calibration();
+
+        irq_mask.masked.store(true, Ordering::SeqCst);
+        let result = run_oscillators_calibration(interface);
+        irq_mask.masked.store(false, Ordering::SeqCst);
+
+        result
+    };
+
+    irq_mask.handle_pending();
+
+    result
+}
+
+pub(crate) fn read_calibration_status(interface: &SharedInterface) -> Result<CalibrationStatus> {
+    let mut interface = interface.lock()?;
+
+    let trco = interface.read(Box::new(TrcoCalibrationDone))? == 0b_1
+        && interface.read(Box::new(TrcoCalibrationNok))? == 0b_0;
+    let srco = interface.read(Box::new(SrcoCalibrationDone))? == 0b_1
+        && interface.read(Box::new(SrcoCalibrationNok))? == 0b_0;
+
+    Ok(CalibrationStatus { trco, srco })
+}
+
+fn run_oscillators_calibration(interface: &SharedInterface) -> Result<()> {
+    debug!("sending CALIB_RCO direct command");
+    interface
+        .lock()?
+        .write(Box::new(CalibrateOscillators), 0x96)?;
+    sleep(Duration::from_millis(2));
+
+    debug!("setting DISP_TRCO=1");
+    interface
+        .lock()?
+        .write(Box::new(DisplayTrcoOnIrqPin), 0b_1)?;
+
+    sleep(CLOCK_GENERATION_DELAY);
+
+    debug!("setting DISP_TRCO=0");
+    interface.lock()?.write(Box::new(DisplayTrcoOnIrqPin), 0)?;
+    sleep(Duration::from_millis(2));
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn noise_floor_levels_map_back_to_their_setting() {
+        for setting in 0..8 {
+            let threshold = NoiseFloorThreshold::new(setting).unwrap();
+
+            for &placing in &[SensorPlacing::Indoor, SensorPlacing::Outdoor] {
+                assert_eq!(
+                    NoiseFloorThreshold::from_microvolts(placing, threshold.microvolts(placing)),
+                    threshold
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn noise_floor_from_microvolts_picks_the_nearest_setting() {
+        let from_microvolts =
+            |placing, microvolts| NoiseFloorThreshold::from_microvolts(placing, microvolts).0;
+
+        assert_eq!(from_microvolts(SensorPlacing::Outdoor, 0), 0);
+        assert_eq!(from_microvolts(SensorPlacing::Outdoor, 700), 1);
+        assert_eq!(from_microvolts(SensorPlacing::Outdoor, 800), 2);
+        assert_eq!(from_microvolts(SensorPlacing::Outdoor, u16::MAX), 7);
+        assert_eq!(from_microvolts(SensorPlacing::Indoor, 100), 4);
+        assert_eq!(from_microvolts(SensorPlacing::Indoor, 2000), 7);
+        // halfway between two levels the lower setting wins
+        assert_eq!(from_microvolts(SensorPlacing::Outdoor, 1120), 3);
+        assert_eq!(from_microvolts(SensorPlacing::Indoor, 70), 2);
+    }
+}
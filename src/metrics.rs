@@ -0,0 +1,417 @@
+use crate::device::registers::Register;
+use crate::interface::{self, Interface};
+use crate::{Event, HeadOfStormDistance, Status};
+use std::fmt::Write as _;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Time a client gets to send its request, so that an idle or slow one can't hold on to a connection.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Upper bounds of the IRQ-to-event latency histogram buckets, in seconds.
+///
+/// Every event takes at least 2 ms to be read out and a lightning another 2 ms for the distance calculation.
+pub const LATENCY_BUCKETS: [f64; 8] = [0.0025, 0.005, 0.0075, 0.01, 0.025, 0.05, 0.1, 0.25];
+
+/// Health of a sensor, updated by the driver once attached with [`AS3935::with_metrics`](crate::AS3935::with_metrics)
+/// and rendered in the Prometheus text format, either directly or by a [`MetricsExporter`].
+pub struct Metrics {
+    lightning: AtomicU64,
+    disturbances: AtomicU64,
+    noise: AtomicU64,
+    sensor_resets: AtomicU64,
+    sensor_unavailable: AtomicU64,
+    recalibrations: AtomicU64,
+    failed_recalibrations: AtomicU64,
+    /// `f64` bits of the last lightning distance in kilometers: 0 when overhead, +Inf when out of range.
+    last_distance: AtomicU64,
+    noise_floor_threshold: AtomicU64,
+    signal_verification_threshold: AtomicU64,
+    read_errors: AtomicU64,
+    write_errors: AtomicU64,
+    latency_buckets: [AtomicU64; LATENCY_BUCKETS.len()],
+    latency_count: AtomicU64,
+    latency_sum_micros: AtomicU64,
+    listening: AtomicBool,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self {
+            lightning: AtomicU64::new(0),
+            disturbances: AtomicU64::new(0),
+            noise: AtomicU64::new(0),
+            sensor_resets: AtomicU64::new(0),
+            sensor_unavailable: AtomicU64::new(0),
+            recalibrations: AtomicU64::new(0),
+            failed_recalibrations: AtomicU64::new(0),
+            last_distance: AtomicU64::new(f64::NAN.to_bits()),
+            noise_floor_threshold: AtomicU64::new(0),
+            signal_verification_threshold: AtomicU64::new(0),
+            read_errors: AtomicU64::new(0),
+            write_errors: AtomicU64::new(0),
+            latency_buckets: Default::default(),
+            latency_count: AtomicU64::new(0),
+            latency_sum_micros: AtomicU64::new(0),
+            listening: AtomicBool::new(false),
+        }
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    /// Records an event together with the time elapsed between the IRQ and the event being read out.
+    pub(crate) fn observe_event(&self, event: &Event, latency: Duration) {
+        match event {
+            Event::Lightning(distance, _) => {
+                self.lightning.fetch_add(1, Ordering::Relaxed);
+
+                let kilometers = match distance {
+                    HeadOfStormDistance::Kilometers(km) => f64::from(*km),
+                    HeadOfStormDistance::Overhead => 0.0,
+                    HeadOfStormDistance::OutOfRange => f64::INFINITY,
+                };
+                self.last_distance
+                    .store(kilometers.to_bits(), Ordering::Relaxed);
+            }
+            Event::Disturbance => {
+                self.disturbances.fetch_add(1, Ordering::Relaxed);
+            }
+            Event::Noise => {
+                self.noise.fetch_add(1, Ordering::Relaxed);
+            }
+            Event::Status(status) => return self.observe_status(status),
+        }
+
+        let seconds = latency.as_secs_f64();
+        for (bucket, bound) in self.latency_buckets.iter().zip(LATENCY_BUCKETS.iter()) {
+            if seconds <= *bound {
+                bucket.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        self.latency_count.fetch_add(1, Ordering::Relaxed);
+        self.latency_sum_micros
+            .fetch_add(latency.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn observe_status(&self, status: &Status) {
+        match status {
+            Status::SensorReset => self.sensor_resets.fetch_add(1, Ordering::Relaxed),
+            Status::SensorUnavailable => self.sensor_unavailable.fetch_add(1, Ordering::Relaxed),
+            Status::Recalibrated(calibration) if calibration.is_successful() => {
+                self.recalibrations.fetch_add(1, Ordering::Relaxed)
+            }
+            Status::Recalibrated(_) => self.failed_recalibrations.fetch_add(1, Ordering::Relaxed),
+        };
+    }
+
+    pub(crate) fn set_noise_floor_threshold(&self, value: u8) {
+        self.noise_floor_threshold
+            .store(u64::from(value), Ordering::Relaxed);
+    }
+
+    pub(crate) fn set_signal_verification_threshold(&self, value: u8) {
+        self.signal_verification_threshold
+            .store(u64::from(value), Ordering::Relaxed);
+    }
+
+    pub(crate) fn set_listening(&self, listening: bool) {
+        self.listening.store(listening, Ordering::Relaxed);
+    }
+
+    /// Renders the metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let load = |value: &AtomicU64| value.load(Ordering::Relaxed).to_string();
+        let mut output = String::new();
+
+        family(
+            &mut output,
+            "as3935_events_total",
+            "counter",
+            "Number of events reported by the sensor.",
+            &[
+                ("{event=\"lightning\"}", load(&self.lightning)),
+                ("{event=\"disturbance\"}", load(&self.disturbances)),
+                ("{event=\"noise\"}", load(&self.noise)),
+            ],
+        );
+
+        family(
+            &mut output,
+            "as3935_sensor_resets_total",
+            "counter",
+            "Number of times the sensor lost its configuration and it was re-applied.",
+            &[("", load(&self.sensor_resets))],
+        );
+
+        family(
+            &mut output,
+            "as3935_sensor_unavailable_total",
+            "counter",
+            "Number of times the sensor stopped responding on the bus.",
+            &[("", load(&self.sensor_unavailable))],
+        );
+
+        family(
+            &mut output,
+            "as3935_recalibrations_total",
+            "counter",
+            "Number of RC oscillators recalibrations while listening.",
+            &[
+                ("{result=\"ok\"}", load(&self.recalibrations)),
+                ("{result=\"failed\"}", load(&self.failed_recalibrations)),
+            ],
+        );
+
+        family(
+            &mut output,
+            "as3935_last_lightning_distance_kilometers",
+            "gauge",
+            "Estimated distance to the head of storm of the last lightning, 0 when overhead and +Inf when out of range.",
+            &[("", format_float(f64::from_bits(self.last_distance.load(Ordering::Relaxed))))],
+        );
+
+        family(
+            &mut output,
+            "as3935_noise_floor_threshold",
+            "gauge",
+            "Configured noise floor threshold (NF_LEV).",
+            &[("", load(&self.noise_floor_threshold))],
+        );
+
+        family(
+            &mut output,
+            "as3935_signal_verification_threshold",
+            "gauge",
+            "Configured watchdog threshold (WDTH).",
+            &[("", load(&self.signal_verification_threshold))],
+        );
+
+        family(
+            &mut output,
+            "as3935_bus_errors_total",
+            "counter",
+            "Number of failed register accesses.",
+            &[
+                ("{operation=\"read\"}", load(&self.read_errors)),
+                ("{operation=\"write\"}", load(&self.write_errors)),
+            ],
+        );
+
+        let buckets = self
+            .latency_buckets
+            .iter()
+            .zip(LATENCY_BUCKETS.iter())
+            .map(|(bucket, bound)| (format!("_bucket{{le=\"{}\"}}", bound), load(bucket)))
+            .collect::<Vec<_>>();
+        let mut samples = buckets
+            .iter()
+            .map(|(labels, value)| (labels.as_str(), value.clone()))
+            .collect::<Vec<_>>();
+        samples.push(("_bucket{le=\"+Inf\"}", load(&self.latency_count)));
+        samples.push((
+            "_sum",
+            (self.latency_sum_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0).to_string(),
+        ));
+        samples.push(("_count", load(&self.latency_count)));
+
+        family(
+            &mut output,
+            "as3935_irq_to_event_seconds",
+            "histogram",
+            "Time between the IRQ and the event being read out.",
+            &samples,
+        );
+
+        family(
+            &mut output,
+            "as3935_listening",
+            "gauge",
+            "Whether the sensor is listening.",
+            &[(
+                "",
+                (self.listening.load(Ordering::Relaxed) as u8).to_string(),
+            )],
+        );
+
+        output
+    }
+}
+
+/// Appends a metric family, the samples are pairs of a name suffix and/or labels and a value.
+fn family(output: &mut String, name: &str, kind: &str, help: &str, samples: &[(&str, String)]) {
+    let _ = writeln!(output, "# HELP {} {}", name, help);
+    let _ = writeln!(output, "# TYPE {} {}", name, kind);
+
+    for (suffix, value) in samples {
+        let _ = writeln!(output, "{}{} {}", name, suffix, value);
+    }
+}
+
+fn format_float(value: f64) -> String {
+    if value.is_nan() {
+        String::from("NaN")
+    } else if value.is_infinite() {
+        String::from(if value > 0.0 { "+Inf" } else { "-Inf" })
+    } else {
+        value.to_string()
+    }
+}
+
+/// Counts the failed register accesses of the wrapped interface.
+pub(crate) struct MeteredInterface {
+    interface: Box<dyn Interface>,
+    metrics: Arc<Metrics>,
+}
+
+impl MeteredInterface {
+    pub(crate) fn new(interface: Box<dyn Interface>, metrics: Arc<Metrics>) -> Self {
+        Self { interface, metrics }
+    }
+}
+
+impl Interface for MeteredInterface {
+    fn read(&mut self, register: Box<dyn Register>) -> interface::Result<u8> {
+        let result = self.interface.read(register);
+        if result.is_err() {
+            self.metrics.read_errors.fetch_add(1, Ordering::Relaxed);
+        }
+
+        result
+    }
+
+    fn write(&mut self, register: Box<dyn Register>, payload: u8) -> interface::Result<()> {
+        let result = self.interface.write(register, payload);
+        if result.is_err() {
+            self.metrics.write_errors.fetch_add(1, Ordering::Relaxed);
+        }
+
+        result
+    }
+}
+
+/// Serves the metrics over HTTP at `/metrics` from a background thread, for as long as the process runs.
+pub struct MetricsExporter {
+    address: SocketAddr,
+}
+
+impl MetricsExporter {
+    pub fn spawn<A: ToSocketAddrs>(address: A, metrics: Arc<Metrics>) -> io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        let address = listener.local_addr()?;
+
+        info!("serving metrics at http://{}/metrics", address);
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        warn!("failed to accept a metrics connection: {}", e);
+                        continue;
+                    }
+                };
+
+                // every connection on its own thread, an idle client must not hold up the next scrape
+                let metrics = metrics.clone();
+                thread::spawn(move || {
+                    if let Err(e) = respond(stream, &metrics) {
+                        warn!("failed to serve metrics: {}", e);
+                    }
+                });
+            }
+        });
+
+        Ok(Self { address })
+    }
+
+    /// Address the exporter is bound to, useful when binding to port 0.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.address
+    }
+}
+
+fn respond(mut stream: TcpStream, metrics: &Metrics) -> io::Result<()> {
+    stream.set_write_timeout(Some(REQUEST_TIMEOUT))?;
+
+    // the read timeout applies to each read, the remaining time bounds the whole request
+    let deadline = Instant::now() + REQUEST_TIMEOUT;
+    let read_line = |reader: &mut BufReader<&TcpStream>, line: &mut String| {
+        let remaining = deadline
+            .checked_duration_since(Instant::now())
+            .filter(|remaining| *remaining > Duration::from_secs(0))
+            .ok_or_else(|| io::Error::new(io::ErrorKind::TimedOut, "request timed out"))?;
+        reader.get_ref().set_read_timeout(Some(remaining))?;
+        reader.read_line(line)
+    };
+
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    read_line(&mut reader, &mut request_line)?;
+
+    // the headers are of no interest, but have to be consumed before responding
+    let mut header = String::new();
+    while read_line(&mut reader, &mut header)? > 0 && !header.trim_end().is_empty() {
+        header.clear();
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (status, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => ("200 OK", metrics.render()),
+        (Some("GET"), Some(_)) => ("404 Not Found", String::from("not found\n")),
+        _ => (
+            "405 Method Not Allowed",
+            String::from("method not allowed\n"),
+        ),
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )?;
+
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn get(address: SocketAddr, path: &str) -> String {
+        let mut stream = TcpStream::connect(address).unwrap();
+        write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn serves_the_metrics() {
+        let exporter = MetricsExporter::spawn("127.0.0.1:0", Metrics::new()).unwrap();
+
+        assert!(get(exporter.local_addr(), "/metrics").starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(get(exporter.local_addr(), "/").starts_with("HTTP/1.1 404 Not Found\r\n"));
+    }
+
+    #[test]
+    fn an_idle_client_does_not_hold_up_a_scrape() {
+        let exporter = MetricsExporter::spawn("127.0.0.1:0", Metrics::new()).unwrap();
+        let _idle = TcpStream::connect(exporter.local_addr()).unwrap();
+
+        let started = Instant::now();
+        assert!(get(exporter.local_addr(), "/metrics").starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(started.elapsed() < REQUEST_TIMEOUT);
+    }
+}
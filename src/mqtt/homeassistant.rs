@@ -0,0 +1,193 @@
+use crate::alert::{AlertEngine, AlertLevel, AlertParameters};
+use crate::mqtt::{MqttParameters, Result, STATUS_OFFLINE, STATUS_ONLINE};
+use crate::{Event, HeadOfStormDistance};
+use serde::Serialize;
+use serde_json::{json, Value};
+
+pub const DEFAULT_DISCOVERY_PREFIX: &str = "homeassistant";
+
+const PAYLOAD_ON: &str = "ON";
+const PAYLOAD_OFF: &str = "OFF";
+
+/// State of the Home Assistant entities, published retained to `<prefix>/<sensor>/state`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+struct State {
+    /// Distance to the head of storm of the last lightning in kilometers, 0 when overhead, `null` when out of range.
+    distance: Option<u8>,
+    energy: Option<u32>,
+    strikes: u64,
+    disturbed: &'static str,
+    storm_active: &'static str,
+}
+
+/// Feeds the Home Assistant entities from the events.
+///
+/// The storm is active for [`DEFAULT_ALL_CLEAR_PERIOD`](crate::alert::DEFAULT_ALL_CLEAR_PERIOD) after any
+/// lightning in range and the sensor is disturbed for
+/// [`DEFAULT_DEGRADED_PERIOD`](crate::alert::DEFAULT_DEGRADED_PERIOD) after a disturber or noise.
+pub(crate) struct HomeAssistant {
+    alert: AlertEngine,
+    state: State,
+}
+
+impl HomeAssistant {
+    pub(crate) fn new() -> Self {
+        let parameters = AlertParameters::default()
+            .with_warning_distance(40)
+            .with_danger_distance(0);
+
+        Self {
+            alert: AlertEngine::new(parameters).expect("valid alert parameters"),
+            state: State {
+                disturbed: PAYLOAD_OFF,
+                storm_active: PAYLOAD_OFF,
+                ..State::default()
+            },
+        }
+    }
+
+    /// Returns the state payload to publish.
+    pub(crate) fn process(&mut self, event: &Event) -> Result<Vec<u8>> {
+        if let Event::Lightning(distance, energy) = event {
+            self.state.distance = match distance {
+                HeadOfStormDistance::Kilometers(km) => Some(*km),
+                HeadOfStormDistance::Overhead => Some(0),
+                HeadOfStormDistance::OutOfRange => None,
+            };
+            self.state.energy = Some(energy.value());
+            self.state.strikes += 1;
+        }
+
+        self.alert.process(event);
+        self.refresh();
+
+        self.payload()
+    }
+
+    /// Returns the state payload to publish if the storm or the disturbance is over.
+    pub(crate) fn update(&mut self) -> Result<Option<Vec<u8>>> {
+        if self.alert.update().is_none() {
+            return Ok(None);
+        }
+
+        self.refresh();
+
+        self.payload().map(Some)
+    }
+
+    pub(crate) fn payload(&self) -> Result<Vec<u8>> {
+        Ok(serde_json::to_vec(&self.state)?)
+    }
+
+    fn refresh(&mut self) {
+        let alert = self.alert.state();
+
+        self.state.disturbed = if alert.degraded {
+            PAYLOAD_ON
+        } else {
+            PAYLOAD_OFF
+        };
+        self.state.storm_active = if alert.level >= AlertLevel::Warning {
+            PAYLOAD_ON
+        } else {
+            PAYLOAD_OFF
+        };
+    }
+}
+
+/// Discovery topics and retained config payloads of the sensor's entities.
+pub(crate) fn discovery(
+    parameters: &MqttParameters,
+    prefix: &str,
+) -> Result<Vec<(String, Vec<u8>)>> {
+    let node_id = format!("{}_{}", parameters.topic_prefix, parameters.sensor).replace('/', "_");
+
+    let device = json!({
+        "identifiers": [node_id],
+        "name": format!("AS3935 {}", parameters.sensor),
+        "model": "AS3935 Franklin Lightning Sensor",
+        "manufacturer": "ams",
+    });
+
+    let entities = [
+        (
+            "sensor",
+            "lightning_distance",
+            json!({
+                "name": "Lightning distance",
+                "device_class": "distance",
+                "unit_of_measurement": "km",
+                "value_template": "{{ value_json.distance }}",
+                "icon": "mdi:map-marker-distance",
+            }),
+        ),
+        (
+            "sensor",
+            "lightning_energy",
+            json!({
+                "name": "Lightning energy",
+                "state_class": "measurement",
+                "value_template": "{{ value_json.energy }}",
+                "icon": "mdi:flash",
+            }),
+        ),
+        (
+            "sensor",
+            "strike_count",
+            json!({
+                "name": "Strike count",
+                "state_class": "total_increasing",
+                "value_template": "{{ value_json.strikes }}",
+                "icon": "mdi:counter",
+            }),
+        ),
+        (
+            "binary_sensor",
+            "disturbed",
+            json!({
+                "name": "Disturber/noise",
+                "device_class": "problem",
+                "value_template": "{{ value_json.disturbed }}",
+            }),
+        ),
+        (
+            "binary_sensor",
+            "storm_active",
+            json!({
+                "name": "Storm active",
+                "device_class": "safety",
+                "value_template": "{{ value_json.storm_active }}",
+                "icon": "mdi:weather-lightning",
+            }),
+        ),
+    ];
+
+    entities
+        .iter()
+        .map(|(component, object_id, entity)| {
+            let mut config = entity.clone();
+            if let Value::Object(fields) = &mut config {
+                fields.insert(
+                    String::from("unique_id"),
+                    json!(format!("{}_{}", node_id, object_id)),
+                );
+                fields.insert(
+                    String::from("state_topic"),
+                    json!(parameters.topic("state")),
+                );
+                fields.insert(
+                    String::from("availability_topic"),
+                    json!(parameters.topic("status")),
+                );
+                fields.insert(String::from("payload_available"), json!(STATUS_ONLINE));
+                fields.insert(String::from("payload_not_available"), json!(STATUS_OFFLINE));
+                fields.insert(String::from("device"), device.clone());
+            }
+
+            Ok((
+                format!("{}/{}/{}/{}/config", prefix, component, node_id, object_id),
+                serde_json::to_vec(&config)?,
+            ))
+        })
+        .collect()
+}
@@ -0,0 +1,606 @@
+use crate::mqtt::homeassistant::HomeAssistant;
+use crate::Event;
+use rumqttc::{Client, ClientError, Connection, Incoming, LastWill, MqttOptions, QoS};
+use serde::Serialize;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::sync::Arc;
+use std::thread::{self, sleep, JoinHandle};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub mod homeassistant;
+
+pub const DEFAULT_PORT: u16 = 1883;
+pub const DEFAULT_TOPIC_PREFIX: &str = "as3935";
+pub const STATUS_ONLINE: &str = "online";
+pub const STATUS_OFFLINE: &str = "offline";
+
+const KEEP_ALIVE: Duration = Duration::from_secs(30);
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+/// How often the forwarding thread checks whether the bridge is being stopped.
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(200);
+const REQUESTS_CAPACITY: usize = 64;
+
+pub type Result<T> = ::std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    Client(ClientError),
+    Serialization(serde_json::Error),
+}
+
+impl ::std::error::Error for Error {}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> ::std::result::Result<(), fmt::Error> {
+        match self {
+            Error::Client(e) => e.fmt(f),
+            Error::Serialization(e) => e.fmt(f),
+        }
+    }
+}
+
+impl From<ClientError> for Error {
+    fn from(error: ClientError) -> Self {
+        Error::Client(error)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(error: serde_json::Error) -> Self {
+        Error::Serialization(error)
+    }
+}
+
+/// Broker connection and topic layout of an [`MqttBridge`].
+///
+/// Events are published to `<prefix>/<sensor>/lightning`, `<prefix>/<sensor>/disturber` and
+/// `<prefix>/<sensor>/noise`. The last lightning is retained in `<prefix>/<sensor>/last_strike` and
+/// `<prefix>/<sensor>/status` holds a retained `online`/`offline` status, which is also the last will and is
+/// set online again on every reconnection.
+/// With Home Assistant discovery enabled, the state of the entities is retained in `<prefix>/<sensor>/state`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MqttParameters {
+    pub(crate) host: String,
+    pub(crate) port: u16,
+    pub(crate) sensor: String,
+    pub(crate) client_id: Option<String>,
+    pub(crate) topic_prefix: String,
+    pub(crate) credentials: Option<(String, String)>,
+    pub(crate) discovery_prefix: Option<String>,
+}
+
+impl MqttParameters {
+    pub fn new<H: Into<String>, S: Into<String>>(host: H, sensor: S) -> Self {
+        Self {
+            host: host.into(),
+            port: DEFAULT_PORT,
+            sensor: sensor.into(),
+            client_id: None,
+            topic_prefix: String::from(DEFAULT_TOPIC_PREFIX),
+            credentials: None,
+            discovery_prefix: None,
+        }
+    }
+
+    pub fn with_port(mut self, port: u16) -> Self {
+        self.port = port;
+        self
+    }
+
+    /// Defaults to `<prefix>-<sensor>`.
+    pub fn with_client_id<S: Into<String>>(mut self, client_id: S) -> Self {
+        self.client_id = Some(client_id.into());
+        self
+    }
+
+    pub fn with_topic_prefix<S: Into<String>>(mut self, topic_prefix: S) -> Self {
+        self.topic_prefix = topic_prefix.into();
+        self
+    }
+
+    pub fn with_credentials<U: Into<String>, P: Into<String>>(
+        mut self,
+        username: U,
+        password: P,
+    ) -> Self {
+        self.credentials = Some((username.into(), password.into()));
+        self
+    }
+
+    /// Announces lightning distance, lightning energy and strike count sensors and disturber/noise and storm
+    /// active binary sensors to Home Assistant, usually under the
+    /// [`DEFAULT_DISCOVERY_PREFIX`](homeassistant::DEFAULT_DISCOVERY_PREFIX).
+    pub fn with_home_assistant_discovery<S: Into<String>>(mut self, discovery_prefix: S) -> Self {
+        self.discovery_prefix = Some(discovery_prefix.into());
+        self
+    }
+
+    /// Full name of a topic belonging to the sensor.
+    pub fn topic(&self, name: &str) -> String {
+        format!("{}/{}/{}", self.topic_prefix, self.sensor, name)
+    }
+
+    fn options(&self) -> MqttOptions {
+        let client_id = self
+            .client_id
+            .clone()
+            .unwrap_or_else(|| format!("{}-{}", self.topic_prefix, self.sensor));
+
+        let mut options = MqttOptions::new(client_id, self.host.clone(), self.port);
+        options.set_keep_alive(KEEP_ALIVE);
+        options.set_last_will(LastWill::new(
+            self.topic("status"),
+            STATUS_OFFLINE,
+            QoS::AtLeastOnce,
+            true,
+        ));
+
+        if let Some((username, password)) = &self.credentials {
+            options.set_credentials(username.clone(), password.clone());
+        }
+
+        options
+    }
+}
+
+/// Payload of the event topics.
+#[derive(Serialize)]
+struct EventMessage<'a> {
+    /// Unix timestamp of the moment the event was published, in seconds.
+    timestamp: u64,
+    event: &'a Event,
+}
+
+/// Publishes the events of a sensor to an MQTT broker.
+pub struct MqttBridge {
+    stopping: Arc<AtomicBool>,
+    forwarder: Option<JoinHandle<()>>,
+    connection: Option<JoinHandle<()>>,
+}
+
+impl MqttBridge {
+    /// Connects to the broker and starts publishing the events received from [`AS3935::listen`](crate::AS3935::listen).
+    /// The bridge goes offline once the events channel is closed or [`stop`](MqttBridge::stop) is called.
+    ///
+    /// The online status and the discovery are published whenever the bridge (re)connects, as the broker
+    /// publishes the offline last will once the connection drops.
+    pub fn spawn(parameters: MqttParameters, events: Receiver<Event>) -> Result<Self> {
+        let (client, connection) = Client::new(parameters.options(), REQUESTS_CAPACITY);
+        let stopping = Arc::new(AtomicBool::new(false));
+        let connected = Arc::new(AtomicBool::new(false));
+
+        let mut publisher = Publisher {
+            client,
+            home_assistant: parameters
+                .discovery_prefix
+                .as_ref()
+                .map(|_| HomeAssistant::new()),
+            parameters,
+        };
+
+        let connection = {
+            let stopping = stopping.clone();
+            let connected = connected.clone();
+            thread::spawn(move || drive(connection, &connected, &stopping))
+        };
+
+        let forwarder = {
+            let stopping = stopping.clone();
+            thread::spawn(move || forward(&mut publisher, &events, &connected, &stopping))
+        };
+
+        Ok(Self {
+            stopping,
+            forwarder: Some(forwarder),
+            connection: Some(connection),
+        })
+    }
+
+    /// Publishes the offline status and disconnects from the broker.
+    pub fn stop(mut self) {
+        self.stopping.store(true, Ordering::SeqCst);
+
+        for handle in self
+            .forwarder
+            .take()
+            .into_iter()
+            .chain(self.connection.take())
+        {
+            if handle.join().is_err() {
+                error!("MQTT bridge thread panicked");
+            }
+        }
+    }
+}
+
+struct Publisher {
+    client: Client,
+    parameters: MqttParameters,
+    home_assistant: Option<HomeAssistant>,
+}
+
+impl Publisher {
+    fn publish_status(&self, status: &str) -> Result<()> {
+        self.client.publish(
+            self.parameters.topic("status"),
+            QoS::AtLeastOnce,
+            true,
+            status,
+        )?;
+
+        Ok(())
+    }
+
+    fn publish_discovery(&self) -> Result<()> {
+        let (prefix, home_assistant) =
+            match (&self.parameters.discovery_prefix, &self.home_assistant) {
+                (Some(prefix), Some(home_assistant)) => (prefix, home_assistant),
+                _ => return Ok(()),
+            };
+
+        for (topic, config) in homeassistant::discovery(&self.parameters, prefix)? {
+            self.client.publish(topic, QoS::AtLeastOnce, true, config)?;
+        }
+
+        self.publish_state(home_assistant.payload()?)
+    }
+
+    fn publish_state(&self, payload: Vec<u8>) -> Result<()> {
+        self.client.publish(
+            self.parameters.topic("state"),
+            QoS::AtLeastOnce,
+            true,
+            payload,
+        )?;
+
+        Ok(())
+    }
+
+    /// Publishes the Home Assistant state once a storm or a disturbance is over.
+    fn publish_update(&mut self) -> Result<()> {
+        let payload = match &mut self.home_assistant {
+            Some(home_assistant) => home_assistant.update()?,
+            None => None,
+        };
+
+        match payload {
+            Some(payload) => self.publish_state(payload),
+            None => Ok(()),
+        }
+    }
+
+    fn publish_event(&mut self, event: &Event) -> Result<()> {
+        let payload = serde_json::to_vec(&EventMessage {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            event,
+        })?;
+
+        let topic = match event {
+            Event::Lightning(..) => {
+                self.client.publish(
+                    self.parameters.topic("last_strike"),
+                    QoS::AtLeastOnce,
+                    true,
+                    payload.clone(),
+                )?;
+
+                "lightning"
+            }
+            Event::Disturbance => "disturber",
+            Event::Noise => "noise",
+            Event::Status(_) => "sensor_status",
+        };
+
+        self.client.publish(
+            self.parameters.topic(topic),
+            QoS::AtLeastOnce,
+            false,
+            payload,
+        )?;
+
+        if let Some(home_assistant) = &mut self.home_assistant {
+            let state = home_assistant.process(event)?;
+            self.publish_state(state)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn forward(
+    publisher: &mut Publisher,
+    events: &Receiver<Event>,
+    connected: &AtomicBool,
+    stopping: &AtomicBool,
+) {
+    while !stopping.load(Ordering::SeqCst) {
+        if connected.swap(false, Ordering::SeqCst) {
+            debug!("MQTT bridge going online");
+
+            if let Err(e) = publisher
+                .publish_status(STATUS_ONLINE)
+                .and_then(|_| publisher.publish_discovery())
+            {
+                warn!("failed to publish the online status: {}", e);
+            }
+        }
+
+        match events.recv_timeout(STOP_POLL_INTERVAL) {
+            Ok(event) => {
+                if let Err(e) = publisher.publish_event(&event) {
+                    warn!("failed to publish event: {}", e);
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                if let Err(e) = publisher.publish_update() {
+                    warn!("failed to publish state: {}", e);
+                }
+            }
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+    }
+
+    debug!("MQTT bridge going offline");
+    stopping.store(true, Ordering::SeqCst);
+
+    if let Err(e) = publisher
+        .publish_status(STATUS_OFFLINE)
+        .and_then(|_| Ok(publisher.client.disconnect()?))
+    {
+        warn!("failed to disconnect from the broker: {}", e);
+    }
+}
+
+/// Keeps the connection to the broker, the forwarding thread publishes the online status once `connected`.
+fn drive(mut connection: Connection, connected: &AtomicBool, stopping: &AtomicBool) {
+    for notification in connection.iter() {
+        match notification {
+            Ok(rumqttc::Event::Incoming(Incoming::ConnAck(_))) => {
+                connected.store(true, Ordering::SeqCst);
+            }
+            Ok(_) => {}
+            Err(e) => {
+                if stopping.load(Ordering::SeqCst) {
+                    break;
+                }
+
+                warn!("MQTT connection error: {}", e);
+                sleep(RECONNECT_DELAY);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{HeadOfStormDistance, LightningEnergy, Status};
+    use bytes::BytesMut;
+    use rumqttc::mqttbytes::{self, v4};
+    use rumqttc::{ConnAck, ConnectReturnCode, Packet, PingResp, PubAck, Publish};
+    use serde_json::Value;
+    use std::io::{Read, Write};
+    use std::net::{Shutdown, TcpListener, TcpStream};
+    use std::sync::mpsc::{channel, Sender};
+    use std::sync::Mutex;
+
+    const RECEIVE_TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// In-process broker which acknowledges everything and reports the packets it received, tagged with the
+    /// number of the connection.
+    struct Broker {
+        port: u16,
+        packets: Receiver<(usize, Packet)>,
+        streams: Arc<Mutex<Vec<TcpStream>>>,
+    }
+
+    impl Broker {
+        fn start() -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let port = listener.local_addr().unwrap().port();
+            let (sender, packets) = channel();
+            let streams = Arc::new(Mutex::new(Vec::new()));
+
+            {
+                let streams = streams.clone();
+
+                thread::spawn(move || {
+                    for (connection, stream) in listener.incoming().enumerate() {
+                        let stream = stream.unwrap();
+                        streams.lock().unwrap().push(stream.try_clone().unwrap());
+
+                        let sender = sender.clone();
+                        thread::spawn(move || serve(connection, stream, &sender));
+                    }
+                });
+            }
+
+            Self {
+                port,
+                packets,
+                streams,
+            }
+        }
+
+        fn parameters(&self) -> MqttParameters {
+            MqttParameters::new("127.0.0.1", "roof").with_port(self.port)
+        }
+
+        fn next(&self) -> (usize, Packet) {
+            self.packets
+                .recv_timeout(RECEIVE_TIMEOUT)
+                .expect("no packet received")
+        }
+
+        /// Skips the packets until the next publish to `topic`.
+        fn publish(&self, topic: &str) -> (usize, Publish) {
+            loop {
+                if let (connection, Packet::Publish(publish)) = self.next() {
+                    if publish.topic == topic {
+                        return (connection, publish);
+                    }
+                }
+            }
+        }
+
+        /// Drops the connections like a network failure would.
+        fn drop_connections(&self) {
+            for stream in self.streams.lock().unwrap().drain(..) {
+                stream.shutdown(Shutdown::Both).unwrap();
+            }
+        }
+    }
+
+    fn serve(connection: usize, mut stream: TcpStream, packets: &Sender<(usize, Packet)>) {
+        let mut buffer = BytesMut::new();
+        let mut chunk = [0; 1024];
+
+        loop {
+            let packet = match v4::read(&mut buffer, 1 << 20) {
+                Ok(packet) => packet,
+                Err(mqttbytes::Error::InsufficientBytes(_)) => match stream.read(&mut chunk) {
+                    Ok(0) | Err(_) => return,
+                    Ok(n) => {
+                        buffer.extend_from_slice(&chunk[..n]);
+                        continue;
+                    }
+                },
+                Err(e) => panic!("malformed packet: {:?}", e),
+            };
+
+            let mut reply = BytesMut::new();
+            match &packet {
+                Packet::Connect(_) => {
+                    ConnAck::new(ConnectReturnCode::Success, false).write(&mut reply)
+                }
+                Packet::Publish(publish) if publish.qos != QoS::AtMostOnce => {
+                    PubAck::new(publish.pkid).write(&mut reply)
+                }
+                Packet::PingReq => PingResp.write(&mut reply),
+                _ => Ok(0),
+            }
+            .unwrap();
+
+            let disconnect = packet == Packet::Disconnect;
+
+            if stream.write_all(&reply).is_err() || packets.send((connection, packet)).is_err() {
+                return;
+            }
+
+            if disconnect {
+                let _ = stream.shutdown(Shutdown::Both);
+                return;
+            }
+        }
+    }
+
+    fn json(publish: &Publish) -> Value {
+        serde_json::from_slice(&publish.payload).unwrap()
+    }
+
+    #[test]
+    fn publishes_events_to_the_sensor_topics() {
+        let broker = Broker::start();
+        let (events, receiver) = channel();
+        let _bridge = MqttBridge::spawn(broker.parameters(), receiver).unwrap();
+
+        match broker.next() {
+            (0, Packet::Connect(connect)) => {
+                assert_eq!(connect.client_id, "as3935-roof");
+
+                let will = connect.last_will.expect("last will");
+                assert_eq!(will.topic, "as3935/roof/status");
+                assert_eq!(&will.message[..], STATUS_OFFLINE.as_bytes());
+                assert!(will.retain);
+            }
+            packet => panic!("expected a connect, got {:?}", packet),
+        }
+
+        let (_, status) = broker.publish("as3935/roof/status");
+        assert_eq!(&status.payload[..], STATUS_ONLINE.as_bytes());
+        assert!(status.retain);
+
+        let lightning =
+            Event::Lightning(HeadOfStormDistance::Kilometers(12), LightningEnergy(1234));
+        events.send(lightning).unwrap();
+
+        let (_, last_strike) = broker.publish("as3935/roof/last_strike");
+        assert!(last_strike.retain);
+        let (_, published) = broker.publish("as3935/roof/lightning");
+        assert!(!published.retain);
+        assert_eq!(published.payload, last_strike.payload);
+
+        let message = json(&published);
+        assert!(message["timestamp"].is_u64());
+        assert_eq!(message["event"], serde_json::to_value(lightning).unwrap());
+
+        for (event, topic) in &[
+            (Event::Disturbance, "as3935/roof/disturber"),
+            (Event::Noise, "as3935/roof/noise"),
+            (
+                Event::Status(Status::SensorReset),
+                "as3935/roof/sensor_status",
+            ),
+        ] {
+            events.send(*event).unwrap();
+
+            let (_, published) = broker.publish(topic);
+            assert!(!published.retain);
+            assert_eq!(
+                json(&published)["event"],
+                serde_json::to_value(event).unwrap()
+            );
+        }
+
+        drop(events);
+
+        let (_, status) = broker.publish("as3935/roof/status");
+        assert_eq!(&status.payload[..], STATUS_OFFLINE.as_bytes());
+        assert!(status.retain);
+
+        loop {
+            if let (_, Packet::Disconnect) = broker.next() {
+                break;
+            }
+        }
+    }
+
+    #[test]
+    fn goes_online_again_after_reconnecting() {
+        let broker = Broker::start();
+        let (_events, receiver) = channel();
+        let parameters = broker
+            .parameters()
+            .with_home_assistant_discovery(homeassistant::DEFAULT_DISCOVERY_PREFIX);
+        let bridge = MqttBridge::spawn(parameters, receiver).unwrap();
+
+        for expected_connection in 0..2 {
+            if expected_connection > 0 {
+                broker.drop_connections();
+            }
+
+            let (connection, status) = broker.publish("as3935/roof/status");
+            assert_eq!(connection, expected_connection);
+            assert_eq!(&status.payload[..], STATUS_ONLINE.as_bytes());
+            assert!(status.retain);
+
+            let (connection, config) =
+                broker.publish("homeassistant/sensor/as3935_roof/lightning_distance/config");
+            assert_eq!(connection, expected_connection);
+            assert!(config.retain);
+            assert_eq!(json(&config)["availability_topic"], "as3935/roof/status");
+            assert_eq!(json(&config)["state_topic"], "as3935/roof/state");
+
+            let (_, state) = broker.publish("as3935/roof/state");
+            assert!(state.retain);
+            assert_eq!(json(&state)["storm_active"], "OFF");
+        }
+
+        bridge.stop();
+    }
+}
@@ -0,0 +1,151 @@
+use crate::{Event, ListeningParameters, Result, AS3935};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// How long the sensor stays awake after a lightning, regardless of the schedule.
+pub const DEFAULT_STORM_HOLD: Duration = Duration::from_secs(30 * 60);
+
+/// How often the [`DutyCycle`] re-evaluates the schedule.
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Saves power by putting a listening sensor to sleep when no storm is likely.
+///
+/// The sensor listens for the awake period and then sleeps for the asleep period, unless a storm is likely or
+/// there was a lightning within the storm hold period, in which case it keeps listening.
+pub struct DutyCycle {
+    awake: Duration,
+    asleep: Duration,
+    storm_hold: Duration,
+    storm_likely: Option<Box<dyn FnMut() -> bool + Send>>,
+}
+
+impl DutyCycle {
+    pub fn new(awake: Duration, asleep: Duration) -> Self {
+        Self {
+            awake,
+            asleep,
+            storm_hold: DEFAULT_STORM_HOLD,
+            storm_likely: None,
+        }
+    }
+
+    pub fn with_storm_hold(mut self, storm_hold: Duration) -> Self {
+        self.storm_hold = storm_hold;
+        self
+    }
+
+    /// Keeps the sensor listening while `storm_likely` returns `true`, e.g. based on a weather forecast.
+    pub fn with_storm_likely<F: FnMut() -> bool + Send + 'static>(
+        mut self,
+        storm_likely: F,
+    ) -> Self {
+        self.storm_likely = Some(Box::new(storm_likely));
+        self
+    }
+
+    /// Starts listening and runs the schedule in a background thread which owns the sensor until stopped.
+    pub fn spawn(
+        self,
+        mut sensor: AS3935,
+        parameters: ListeningParameters,
+    ) -> Result<(DutyCycleHandle, Receiver<Event>)> {
+        let events = sensor.listen(parameters)?;
+
+        let (sender, receiver) = channel::<Event>();
+        let stopping = Arc::new(AtomicBool::new(false));
+
+        let thread = {
+            let stopping = stopping.clone();
+            thread::spawn(move || self.run(sensor, &events, &sender, &stopping))
+        };
+
+        Ok((DutyCycleHandle { stopping, thread }, receiver))
+    }
+
+    fn run(
+        mut self,
+        mut sensor: AS3935,
+        events: &Receiver<Event>,
+        sender: &Sender<Event>,
+        stopping: &AtomicBool,
+    ) -> AS3935 {
+        let mut since = Instant::now();
+        let mut last_lightning: Option<Instant> = None;
+
+        while !stopping.load(Ordering::SeqCst) {
+            match events.recv_timeout(CHECK_INTERVAL) {
+                Ok(event) => {
+                    if let Event::Lightning(..) = event {
+                        last_lightning = Some(Instant::now());
+                    }
+
+                    if sender.send(event).is_err() {
+                        break;
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+
+            let storm = match last_lightning {
+                Some(at) => at.elapsed() < self.storm_hold,
+                None => false,
+            };
+            let likely = match &mut self.storm_likely {
+                Some(storm_likely) => storm_likely(),
+                None => false,
+            };
+
+            let result = if sensor.is_listening() {
+                if storm || likely || since.elapsed() < self.awake {
+                    continue;
+                }
+
+                info!("no storm likely, putting the sensor to sleep");
+                sensor.sleep()
+            } else {
+                if !likely && since.elapsed() < self.asleep {
+                    continue;
+                }
+
+                info!("waking the sensor up");
+                sensor.wake().map(|calibration| {
+                    if !calibration.is_successful() {
+                        warn!("oscillators calibration failed: {:?}", calibration);
+                    }
+                })
+            };
+
+            match result {
+                Ok(()) => since = Instant::now(),
+                Err(e) => error!("failed to change the sensor's power state: {}", e),
+            }
+        }
+
+        if let Err(e) = sensor.terminate() {
+            error!("failed to terminate the sensor: {}", e);
+        }
+
+        sensor
+    }
+}
+
+pub struct DutyCycleHandle {
+    stopping: Arc<AtomicBool>,
+    thread: JoinHandle<AS3935>,
+}
+
+impl DutyCycleHandle {
+    /// Stops the schedule and the listening, returns the powered down sensor.
+    pub fn stop(self) -> AS3935 {
+        self.stopping.store(true, Ordering::SeqCst);
+
+        match self.thread.join() {
+            Ok(sensor) => sensor,
+            Err(panic) => ::std::panic::resume_unwind(panic),
+        }
+    }
+}
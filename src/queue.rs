@@ -0,0 +1,300 @@
+use crate::Event;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+
+/// What happens to an event which arrives while a bounded event queue is full.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum OverflowPolicy {
+    /// The oldest queued event is dropped to make room.
+    DropOldest,
+    /// The arriving event is dropped.
+    DropNewest,
+    /// A disturbance is merged into a queued one, other events make room by dropping the oldest queued
+    /// disturbance, or the oldest event if there is none, so that lightning survives disturber bursts.
+    CoalesceDisturbers,
+}
+
+/// Counters of the events which did not make it through the event queue, see
+/// [`AS3935::with_event_queue`](crate::AS3935::with_event_queue).
+#[derive(Debug, Default)]
+pub struct QueueStats {
+    dropped: AtomicU64,
+    coalesced: AtomicU64,
+}
+
+impl QueueStats {
+    /// Events dropped because the queue was full.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Disturbances merged into an already queued one because the queue was full.
+    pub fn coalesced(&self) -> u64 {
+        self.coalesced.load(Ordering::Relaxed)
+    }
+}
+
+/// Sending side of the events channel, shared by the IRQ handler and the driver's background threads.
+#[derive(Clone)]
+pub(crate) enum EventSender {
+    Unbounded(Sender<Event>),
+    Bounded(Arc<BoundedSender>),
+}
+
+impl EventSender {
+    /// Queues the event, returns `false` once the receiver was dropped.
+    pub(crate) fn send(&self, event: Event) -> bool {
+        match self {
+            EventSender::Unbounded(sender) => sender.send(event).is_ok(),
+            EventSender::Bounded(sender) => sender.queue.push(event),
+        }
+    }
+}
+
+/// Closes the queue once the last sender is gone, the receiver gets the queued events first.
+pub(crate) struct BoundedSender {
+    queue: Arc<Queue>,
+}
+
+impl Drop for BoundedSender {
+    fn drop(&mut self) {
+        self.queue.state.lock().unwrap().senders_gone = true;
+        self.queue.ready.notify_one();
+    }
+}
+
+struct Queue {
+    capacity: usize,
+    policy: OverflowPolicy,
+    stats: Arc<QueueStats>,
+    state: Mutex<State>,
+    ready: Condvar,
+}
+
+#[derive(Default)]
+struct State {
+    events: VecDeque<Event>,
+    senders_gone: bool,
+    receiver_gone: bool,
+}
+
+impl Queue {
+    fn push(&self, event: Event) -> bool {
+        let mut state = self.state.lock().unwrap();
+
+        if state.receiver_gone {
+            return false;
+        }
+
+        if state.events.len() >= self.capacity && !self.make_room(&mut state.events, &event) {
+            return true;
+        }
+
+        state.events.push_back(event);
+        self.ready.notify_one();
+
+        true
+    }
+
+    /// Applies the overflow policy to the full queue, returns whether the event should still be queued.
+    fn make_room(&self, events: &mut VecDeque<Event>, event: &Event) -> bool {
+        let disturbance = |queued: &Event| *queued == Event::Disturbance;
+
+        match self.policy {
+            OverflowPolicy::DropOldest => {
+                events.pop_front();
+            }
+            OverflowPolicy::DropNewest => {
+                self.stats.dropped.fetch_add(1, Ordering::Relaxed);
+                return false;
+            }
+            OverflowPolicy::CoalesceDisturbers => {
+                let queued_disturbance = events.iter().position(disturbance);
+
+                if disturbance(event) && queued_disturbance.is_some() {
+                    self.stats.coalesced.fetch_add(1, Ordering::Relaxed);
+                    return false;
+                }
+
+                match queued_disturbance {
+                    Some(index) => events.remove(index),
+                    None => events.pop_front(),
+                };
+            }
+        }
+
+        self.stats.dropped.fetch_add(1, Ordering::Relaxed);
+        true
+    }
+
+    /// Next event to deliver, waits for one while there are senders.
+    fn pop(&self) -> Option<Event> {
+        let mut state = self.state.lock().unwrap();
+
+        loop {
+            if let Some(event) = state.events.pop_front() {
+                return Some(event);
+            }
+
+            if state.senders_gone {
+                return None;
+            }
+
+            state = self.ready.wait(state).unwrap();
+        }
+    }
+}
+
+/// Creates the events channel of a listening sensor, bounded to `capacity` events if set.
+///
+/// The bounded queue is drained into a rendezvous channel by a thread, so that the consumer keeps receiving the
+/// events through a plain [`Receiver`]. The thread holds one more event than `capacity` while the receiver
+/// doesn't take it.
+pub(crate) fn channel(
+    capacity: Option<(usize, OverflowPolicy)>,
+    stats: Arc<QueueStats>,
+) -> (EventSender, Receiver<Event>) {
+    let (capacity, policy) = match capacity {
+        Some(capacity) => capacity,
+        None => {
+            let (sender, receiver) = mpsc::channel::<Event>();
+            return (EventSender::Unbounded(sender), receiver);
+        }
+    };
+
+    let queue = Arc::new(Queue {
+        capacity,
+        policy,
+        stats,
+        state: Mutex::new(State::default()),
+        ready: Condvar::new(),
+    });
+    let (sender, receiver) = mpsc::sync_channel::<Event>(0);
+
+    {
+        let queue = queue.clone();
+
+        thread::spawn(move || {
+            while let Some(event) = queue.pop() {
+                if sender.send(event).is_err() {
+                    let mut state = queue.state.lock().unwrap();
+                    state.receiver_gone = true;
+                    state.events.clear();
+                    return;
+                }
+            }
+        });
+    }
+
+    (
+        EventSender::Bounded(Arc::new(BoundedSender { queue })),
+        receiver,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{HeadOfStormDistance, LightningEnergy};
+    use std::time::{Duration, Instant};
+
+    fn queue(capacity: usize, policy: OverflowPolicy) -> Queue {
+        Queue {
+            capacity,
+            policy,
+            stats: Arc::new(QueueStats::default()),
+            state: Mutex::new(State::default()),
+            ready: Condvar::new(),
+        }
+    }
+
+    fn lightning(km: u8) -> Event {
+        Event::Lightning(HeadOfStormDistance::Kilometers(km), LightningEnergy(1000))
+    }
+
+    fn queued(queue: &Queue) -> Vec<Event> {
+        queue.state.lock().unwrap().events.iter().cloned().collect()
+    }
+
+    #[test]
+    fn drop_oldest_makes_room_for_the_arriving_event() {
+        let queue = queue(2, OverflowPolicy::DropOldest);
+
+        for event in &[lightning(10), Event::Disturbance, Event::Noise] {
+            assert!(queue.push(*event));
+        }
+
+        assert_eq!(queued(&queue), vec![Event::Disturbance, Event::Noise]);
+        assert_eq!(queue.stats.dropped(), 1);
+        assert_eq!(queue.stats.coalesced(), 0);
+    }
+
+    #[test]
+    fn drop_newest_keeps_the_queued_events() {
+        let queue = queue(2, OverflowPolicy::DropNewest);
+
+        for event in &[lightning(10), Event::Disturbance, Event::Noise] {
+            assert!(queue.push(*event));
+        }
+
+        assert_eq!(queued(&queue), vec![lightning(10), Event::Disturbance]);
+        assert_eq!(queue.stats.dropped(), 1);
+    }
+
+    #[test]
+    fn coalesce_disturbers_lets_lightning_survive_disturber_bursts() {
+        let queue = queue(2, OverflowPolicy::CoalesceDisturbers);
+
+        queue.push(Event::Disturbance);
+        queue.push(lightning(10));
+        queue.push(Event::Disturbance);
+        assert_eq!(queued(&queue), vec![Event::Disturbance, lightning(10)]);
+        assert_eq!(queue.stats.coalesced(), 1);
+        assert_eq!(queue.stats.dropped(), 0);
+
+        queue.push(lightning(5));
+        assert_eq!(queued(&queue), vec![lightning(10), lightning(5)]);
+        assert_eq!(queue.stats.dropped(), 1);
+
+        // without a queued disturbance, the oldest event makes room
+        queue.push(Event::Noise);
+        assert_eq!(queued(&queue), vec![lightning(5), Event::Noise]);
+        assert_eq!(queue.stats.dropped(), 2);
+        assert_eq!(queue.stats.coalesced(), 1);
+    }
+
+    #[test]
+    fn delivers_the_queued_events_before_closing() {
+        let (sender, receiver) = channel(
+            Some((4, OverflowPolicy::DropNewest)),
+            Arc::new(QueueStats::default()),
+        );
+
+        assert!(sender.send(lightning(10)));
+        assert!(sender.send(Event::Noise));
+        drop(sender);
+
+        assert_eq!(
+            receiver.iter().collect::<Vec<_>>(),
+            vec![lightning(10), Event::Noise]
+        );
+    }
+
+    #[test]
+    fn stops_queueing_once_the_receiver_is_dropped() {
+        let (sender, receiver) = channel(
+            Some((4, OverflowPolicy::DropNewest)),
+            Arc::new(QueueStats::default()),
+        );
+        drop(receiver);
+
+        let started = Instant::now();
+        while sender.send(Event::Noise) {
+            assert!(started.elapsed() < Duration::from_secs(5));
+        }
+    }
+}
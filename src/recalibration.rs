@@ -0,0 +1,130 @@
+use crate::watchdog::WatchdogContext;
+use crate::{calibrate_oscillators, read_calibration_status, Event, Status, AS3935};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// When the RC oscillators of a listening sensor are recalibrated, see [`AS3935::with_recalibration`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Recalibration {
+    /// Recalibrates every interval.
+    Every(Duration),
+    /// Reads the calibration status every interval and recalibrates when it's not successful.
+    WhenUncalibrated(Duration),
+}
+
+impl Recalibration {
+    fn interval(self) -> Duration {
+        match self {
+            Recalibration::Every(interval) | Recalibration::WhenUncalibrated(interval) => interval,
+        }
+    }
+}
+
+impl AS3935 {
+    /// While listening, recalibrates the RC oscillators according to `recalibration`, as they drift with the
+    /// temperature. The IRQ handler ignores the IRQ pin while it displays TRCO, and every recalibration is
+    /// reported as [`Status::Recalibrated`].
+    pub fn with_recalibration(mut self, recalibration: Recalibration) -> Self {
+        self.recalibrator = Some(Recalibrator {
+            recalibration,
+            running: None,
+        });
+        self
+    }
+}
+
+pub(crate) struct Recalibrator {
+    recalibration: Recalibration,
+    running: Option<Running>,
+}
+
+struct Running {
+    /// Cleared while the sensor sleeps, as it is recalibrated when woken up anyway.
+    active: Arc<AtomicBool>,
+    stop: Sender<()>,
+    thread: JoinHandle<()>,
+}
+
+impl Recalibrator {
+    pub(crate) fn start(&mut self, context: WatchdogContext) {
+        self.stop();
+
+        let active = Arc::new(AtomicBool::new(true));
+        let (stop, stopped) = channel::<()>();
+        let recalibration = self.recalibration;
+
+        let thread = {
+            let active = active.clone();
+
+            thread::spawn(move || loop {
+                match stopped.recv_timeout(recalibration.interval()) {
+                    Err(RecvTimeoutError::Timeout) => {}
+                    _ => return,
+                }
+
+                if !active.load(Ordering::SeqCst) {
+                    continue;
+                }
+
+                if let Recalibration::WhenUncalibrated(_) = recalibration {
+                    match read_calibration_status(&context.interface) {
+                        Ok(status) if status.is_successful() => continue,
+                        Ok(status) => info!("oscillators calibration lost: {:?}", status),
+                        Err(e) => {
+                            warn!("failed to read the calibration status: {}", e);
+                            continue;
+                        }
+                    }
+                }
+
+                debug!("recalibrating the RC oscillators");
+
+                let status = calibrate_oscillators(&context.interface, &context.irq_mask)
+                    .and_then(|_| read_calibration_status(&context.interface));
+
+                match status {
+                    Ok(status) => {
+                        if !status.is_successful() {
+                            warn!("oscillators recalibration failed: {:?}", status);
+                        }
+
+                        let status = Status::Recalibrated(status);
+
+                        if let Some(metrics) = &context.metrics {
+                            metrics.observe_status(&status);
+                        }
+
+                        context.subscribers.publish(Event::Status(status));
+                    }
+                    Err(e) => warn!("failed to recalibrate the RC oscillators: {}", e),
+                }
+            })
+        };
+
+        self.running = Some(Running {
+            active,
+            stop,
+            thread,
+        });
+    }
+
+    pub(crate) fn set_active(&self, active: bool) {
+        if let Some(running) = &self.running {
+            running.active.store(active, Ordering::SeqCst);
+        }
+    }
+
+    pub(crate) fn stop(&mut self) {
+        if let Some(running) = self.running.take() {
+            drop(running.stop);
+
+            if running.thread.join().is_err() {
+                error!("recalibration thread panicked");
+            }
+        }
+    }
+}
@@ -0,0 +1,739 @@
+//! Recording of sensor sessions and their replay through the driver.
+//!
+//! A recording is a text file which starts with a `as3935-recording <version>` header followed by the bus
+//! line and one line per entry, each prefixed with the number of microseconds since the recording started:
+//!
+//! ```text
+//! as3935-recording 1
+//! B i2c 1 3
+//! 1520 W 00 01 00
+//! 3611 R 03 0f 08
+//! 3620 R 07 3f !i2c error message
+//! 9000 A
+//! 9011 I 1
+//! 9502 E lightning 12 3456
+//! 9600 D
+//! ```
+//!
+//! `R`/`W` are register reads and writes (address, mask and value in hex, failures follow `!`), `A`/`D`
+//! arming and disarming of the IRQ, `I` IRQ edges with the pin level and `E` decoded events.
+
+use crate::device::registers::Register;
+use crate::interface::{self, Bus, Interface, IrqCallback, IrqSource};
+use crate::{CalibrationStatus, Event, HeadOfStormDistance, LightningEnergy, Status, AS3935};
+use rppal::gpio::{Level, Trigger};
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+pub const FORMAT_VERSION: u32 = 1;
+
+const HEADER: &str = "as3935-recording";
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    /// The file is not a recording, or a line could not be parsed.
+    Parse {
+        line: usize,
+        reason: String,
+    },
+    UnsupportedVersion(u32),
+}
+
+impl ::std::error::Error for Error {}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> ::std::result::Result<(), fmt::Error> {
+        match self {
+            Error::Io(e) => e.fmt(f),
+            Error::Parse { line, reason } => write!(f, "line {}: {}", line, reason),
+            Error::UnsupportedVersion(version) => {
+                write!(f, "unsupported recording format version {}", version)
+            }
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+pub type Result<T> = ::std::result::Result<T, Error>;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Record {
+    Bus(Bus),
+    Read {
+        address: u8,
+        mask: u8,
+        value: ::std::result::Result<u8, String>,
+    },
+    Write {
+        address: u8,
+        mask: u8,
+        payload: u8,
+        error: Option<String>,
+    },
+    Armed,
+    Disarmed,
+    Irq(Level),
+    Event(Event),
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter) -> ::std::result::Result<(), fmt::Error> {
+        match self {
+            Record::Bus(Bus::I2c { bus, address }) => write!(f, "B i2c {} {}", bus, address),
+            Record::Bus(Bus::Spi { bus, chip_select }) => {
+                write!(f, "B spi {} {}", bus, chip_select)
+            }
+            Record::Read {
+                address,
+                mask,
+                value,
+            } => match value {
+                Ok(value) => write!(f, "R {:02x} {:02x} {:02x}", address, mask, value),
+                Err(e) => write!(f, "R {:02x} {:02x} !{}", address, mask, e),
+            },
+            Record::Write {
+                address,
+                mask,
+                payload,
+                error,
+            } => {
+                write!(f, "W {:02x} {:02x} {:02x}", address, mask, payload)?;
+                match error {
+                    Some(e) => write!(f, " !{}", e),
+                    None => Ok(()),
+                }
+            }
+            Record::Armed => write!(f, "A"),
+            Record::Disarmed => write!(f, "D"),
+            Record::Irq(Level::Low) => write!(f, "I 0"),
+            Record::Irq(Level::High) => write!(f, "I 1"),
+            Record::Event(Event::Lightning(distance, energy)) => {
+                write!(f, "E lightning ")?;
+                match distance {
+                    HeadOfStormDistance::Kilometers(km) => write!(f, "{}", km)?,
+                    HeadOfStormDistance::OutOfRange => write!(f, "out_of_range")?,
+                    HeadOfStormDistance::Overhead => write!(f, "overhead")?,
+                }
+                write!(f, " {}", energy.value())
+            }
+            Record::Event(Event::Disturbance) => write!(f, "E disturbance"),
+            Record::Event(Event::Noise) => write!(f, "E noise"),
+            Record::Event(Event::Status(Status::SensorReset)) => write!(f, "E status sensor_reset"),
+            Record::Event(Event::Status(Status::SensorUnavailable)) => {
+                write!(f, "E status sensor_unavailable")
+            }
+            Record::Event(Event::Status(Status::Recalibrated(calibration))) => write!(
+                f,
+                "E status recalibrated {} {}",
+                calibration.trco as u8, calibration.srco as u8
+            ),
+        }
+    }
+}
+
+impl Record {
+    fn parse(fields: &[&str]) -> ::std::result::Result<Self, String> {
+        let hex = |field: &str| {
+            u8::from_str_radix(field, 16).map_err(|_| format!("invalid hex value {}", field))
+        };
+        let number = |field: &str| {
+            field
+                .parse::<u32>()
+                .map_err(|_| format!("invalid number {}", field))
+        };
+        let byte = |field: &str| {
+            number(field)
+                .and_then(|n| u8::try_from(n).map_err(|_| format!("number {} out of range", field)))
+        };
+        // error messages may contain spaces, so everything after the `!` belongs to them
+        let failure = |rest: &[&str]| match rest.first() {
+            Some(first) if first.starts_with('!') => Some(rest.join(" ")[1..].to_string()),
+            _ => None,
+        };
+
+        Ok(match fields {
+            ["B", "i2c", bus, address] => Record::Bus(Bus::I2c {
+                bus: byte(bus)?,
+                address: byte(address)?,
+            }),
+            ["B", "spi", bus, chip_select] => Record::Bus(Bus::Spi {
+                bus: byte(bus)?,
+                chip_select: byte(chip_select)?,
+            }),
+            ["R", address, mask, rest @ ..] if !rest.is_empty() => Record::Read {
+                address: hex(address)?,
+                mask: hex(mask)?,
+                value: match failure(rest) {
+                    Some(e) => Err(e),
+                    None => Ok(hex(rest[0])?),
+                },
+            },
+            ["W", address, mask, payload, rest @ ..] => Record::Write {
+                address: hex(address)?,
+                mask: hex(mask)?,
+                payload: hex(payload)?,
+                error: failure(rest),
+            },
+            ["A"] => Record::Armed,
+            ["D"] => Record::Disarmed,
+            ["I", "0"] => Record::Irq(Level::Low),
+            ["I", "1"] => Record::Irq(Level::High),
+            ["E", "disturbance"] => Record::Event(Event::Disturbance),
+            ["E", "noise"] => Record::Event(Event::Noise),
+            ["E", "status", "sensor_reset"] => Record::Event(Event::Status(Status::SensorReset)),
+            ["E", "status", "sensor_unavailable"] => {
+                Record::Event(Event::Status(Status::SensorUnavailable))
+            }
+            ["E", "status", "recalibrated", trco, srco] => {
+                Record::Event(Event::Status(Status::Recalibrated(CalibrationStatus {
+                    trco: number(trco)? == 1,
+                    srco: number(srco)? == 1,
+                })))
+            }
+            ["E", "lightning", distance, energy] => Record::Event(Event::Lightning(
+                match *distance {
+                    "out_of_range" => HeadOfStormDistance::OutOfRange,
+                    "overhead" => HeadOfStormDistance::Overhead,
+                    km => HeadOfStormDistance::Kilometers(byte(km)?),
+                },
+                LightningEnergy(number(energy)?),
+            )),
+            _ => return Err(format!("unknown entry {}", fields.join(" "))),
+        })
+    }
+}
+
+/// Writes the bus transactions, IRQ edges and events of a sensor attached with
+/// [`AS3935::with_recorder`](crate::AS3935::with_recorder).
+pub struct Recorder {
+    started_at: Instant,
+    writer: Mutex<Box<dyn Write + Send>>,
+}
+
+impl Recorder {
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Arc<Self>> {
+        Self::new(BufWriter::new(File::create(path)?))
+    }
+
+    pub fn new<W: Write + Send + 'static>(mut writer: W) -> io::Result<Arc<Self>> {
+        writeln!(writer, "{} {}", HEADER, FORMAT_VERSION)?;
+
+        Ok(Arc::new(Self {
+            started_at: Instant::now(),
+            writer: Mutex::new(Box::new(writer)),
+        }))
+    }
+
+    pub(crate) fn record_event(&self, event: &Event) {
+        self.record(&Record::Event(*event));
+    }
+
+    fn record(&self, record: &Record) {
+        let at = self.started_at.elapsed().as_micros();
+        let mut writer = self.writer.lock().unwrap();
+
+        let result = match record {
+            Record::Bus(_) => writeln!(writer, "{}", record),
+            _ => writeln!(writer, "{} {}", at, record),
+        };
+
+        // bus transactions are frequent, the rest is flushed right away so that it is not lost on a crash
+        let result = match record {
+            Record::Read { .. } | Record::Write { .. } | Record::Irq(_) => result,
+            _ => result.and_then(|_| writer.flush()),
+        };
+
+        if let Err(e) = result {
+            warn!("failed to write the recording: {}", e);
+        }
+    }
+}
+
+impl AS3935 {
+    /// Records the bus transactions, IRQ edges and events of the sensor.
+    pub fn with_recorder(mut self, recorder: Arc<Recorder>) -> Self {
+        recorder.record(&Record::Bus(self.bus));
+
+        let interface_recorder = recorder.clone();
+        self.decorate_interface(move |interface| {
+            Box::new(RecordingInterface {
+                interface,
+                recorder: interface_recorder,
+            })
+        });
+
+        let irq_recorder = recorder.clone();
+        self.decorate_irq_source(move |irq_source| {
+            Box::new(RecordingIrqSource {
+                irq_source,
+                recorder: irq_recorder,
+            })
+        });
+
+        self.recorder = Some(recorder);
+        self
+    }
+}
+
+struct RecordingInterface {
+    interface: Box<dyn Interface>,
+    recorder: Arc<Recorder>,
+}
+
+impl Interface for RecordingInterface {
+    fn read(&mut self, register: Box<dyn Register>) -> interface::Result<u8> {
+        let (address, mask) = (register.address(), register.mask());
+        let result = self.interface.read(register);
+
+        self.recorder.record(&Record::Read {
+            address,
+            mask,
+            value: result
+                .as_ref()
+                .map(|value| *value)
+                .map_err(ToString::to_string),
+        });
+
+        result
+    }
+
+    fn write(&mut self, register: Box<dyn Register>, payload: u8) -> interface::Result<()> {
+        let (address, mask) = (register.address(), register.mask());
+        let result = self.interface.write(register, payload);
+
+        self.recorder.record(&Record::Write {
+            address,
+            mask,
+            payload,
+            error: result.as_ref().err().map(ToString::to_string),
+        });
+
+        result
+    }
+}
+
+struct RecordingIrqSource {
+    irq_source: Box<dyn IrqSource>,
+    recorder: Arc<Recorder>,
+}
+
+impl IrqSource for RecordingIrqSource {
+    fn set_async_interrupt(
+        &mut self,
+        trigger: Trigger,
+        mut callback: IrqCallback,
+    ) -> crate::Result<()> {
+        let recorder = self.recorder.clone();
+
+        self.irq_source.set_async_interrupt(
+            trigger,
+            Box::new(move |level: Level| {
+                recorder.record(&Record::Irq(level));
+                callback(level);
+            }),
+        )?;
+        self.recorder.record(&Record::Armed);
+
+        Ok(())
+    }
+
+    fn clear_async_interrupt(&mut self) -> crate::Result<()> {
+        self.irq_source.clear_async_interrupt()?;
+        self.recorder.record(&Record::Disarmed);
+
+        Ok(())
+    }
+
+    fn pin(&self) -> Option<u8> {
+        self.irq_source.pin()
+    }
+
+    fn counts_edges(&self) -> bool {
+        self.irq_source.counts_edges()
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ReplaySpeed {
+    /// IRQ edges are replayed with the recorded delays between them.
+    RealTime,
+    AsFastAsPossible,
+}
+
+/// A recorded sensor session.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Recording {
+    bus: Bus,
+    entries: Vec<(Duration, Record)>,
+}
+
+impl Recording {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::parse(BufReader::new(File::open(path)?))
+    }
+
+    pub fn parse<R: BufRead>(reader: R) -> Result<Self> {
+        let mut lines = reader.lines();
+        let parse_error = |line: usize, reason: String| Error::Parse { line, reason };
+
+        let header = lines.next().transpose()?.unwrap_or_default();
+        let version = match header.split_whitespace().collect::<Vec<_>>().as_slice() {
+            [HEADER, version] => version
+                .parse::<u32>()
+                .map_err(|_| parse_error(1, format!("invalid version {}", version)))?,
+            _ => return Err(parse_error(1, String::from("not an AS3935 recording"))),
+        };
+        if version != FORMAT_VERSION {
+            return Err(Error::UnsupportedVersion(version));
+        }
+
+        let mut bus = None;
+        let mut entries = Vec::new();
+
+        for (index, line) in lines.enumerate() {
+            let (number, line) = (index + 2, line?);
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+
+            if fields.is_empty() {
+                continue;
+            }
+
+            if fields[0] == "B" {
+                match Record::parse(&fields).map_err(|e| parse_error(number, e))? {
+                    Record::Bus(recorded) => bus = Some(recorded),
+                    _ => unreachable!(),
+                }
+                continue;
+            }
+
+            let at = fields[0]
+                .parse::<u64>()
+                .map_err(|_| parse_error(number, format!("invalid timestamp {}", fields[0])))?;
+            let record = Record::parse(&fields[1..]).map_err(|e| parse_error(number, e))?;
+
+            entries.push((Duration::from_micros(at), record));
+        }
+
+        Ok(Self {
+            bus: bus.ok_or_else(|| parse_error(2, String::from("missing bus line")))?,
+            entries,
+        })
+    }
+
+    pub fn bus(&self) -> Bus {
+        self.bus
+    }
+
+    /// Events decoded while recording, with the time they were recorded at.
+    pub fn events(&self) -> Vec<(Duration, Event)> {
+        self.entries
+            .iter()
+            .filter_map(|(at, record)| match record {
+                Record::Event(event) => Some((*at, *event)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Creates a sensor which plays the recording back.
+    ///
+    /// Register reads are answered with the next recorded read of the same register and writes are checked
+    /// off against the recorded ones; every time the IRQ is armed, the IRQ edges of the next recorded listening
+    /// session are replayed. The events channel closes once the edges of the session run out.
+    pub fn replay(self, speed: ReplaySpeed) -> AS3935 {
+        let entries = Arc::new(self.entries);
+
+        AS3935::from_parts(
+            self.bus,
+            Box::new(ReplayInterface {
+                entries: entries.clone(),
+                cursor: 0,
+            }),
+            Box::new(ReplayIrqSource {
+                entries,
+                speed,
+                sessions: 0,
+                playback: None,
+            }),
+        )
+    }
+}
+
+struct ReplayInterface {
+    entries: Arc<Vec<(Duration, Record)>>,
+    cursor: usize,
+}
+
+impl ReplayInterface {
+    fn find<P: Fn(&Record) -> bool>(&self, predicate: P) -> Option<usize> {
+        self.entries[self.cursor..]
+            .iter()
+            .position(|(_, record)| predicate(record))
+            .map(|position| self.cursor + position)
+    }
+}
+
+impl Interface for ReplayInterface {
+    fn read(&mut self, register: Box<dyn Register>) -> interface::Result<u8> {
+        let (address, mask) = (register.address(), register.mask());
+
+        let index = self
+            .find(|record| match record {
+                Record::Read {
+                    address: a,
+                    mask: m,
+                    ..
+                } => *a == address && *m == mask,
+                _ => false,
+            })
+            .ok_or_else(|| {
+                interface::Error::Replay(format!("no more recorded reads of {}", register.name()))
+            })?;
+
+        self.cursor = index + 1;
+
+        match &self.entries[index].1 {
+            Record::Read { value, .. } => value.clone().map_err(interface::Error::Replay),
+            _ => unreachable!(),
+        }
+    }
+
+    fn write(&mut self, register: Box<dyn Register>, payload: u8) -> interface::Result<()> {
+        let (address, mask) = (register.address(), register.mask());
+
+        let index = self.find(|record| match record {
+            Record::Write {
+                address: a,
+                mask: m,
+                ..
+            } => *a == address && *m == mask,
+            _ => false,
+        });
+
+        match index {
+            Some(index) => {
+                self.cursor = index + 1;
+
+                match &self.entries[index].1 {
+                    Record::Write {
+                        payload: recorded,
+                        error,
+                        ..
+                    } => {
+                        if *recorded != payload {
+                            debug!(
+                                "replay: {} written with {:#x}, recorded {:#x}",
+                                register.name(),
+                                payload,
+                                recorded
+                            );
+                        }
+
+                        match error {
+                            Some(e) => Err(interface::Error::Replay(e.clone())),
+                            None => Ok(()),
+                        }
+                    }
+                    _ => unreachable!(),
+                }
+            }
+            None => {
+                debug!(
+                    "replay: {} was not written in the recording",
+                    register.name()
+                );
+                Ok(())
+            }
+        }
+    }
+}
+
+struct ReplayIrqSource {
+    entries: Arc<Vec<(Duration, Record)>>,
+    speed: ReplaySpeed,
+    /// Number of times the IRQ has been armed.
+    sessions: usize,
+    playback: Option<(Sender<()>, JoinHandle<()>)>,
+}
+
+impl ReplayIrqSource {
+    /// Start and IRQ edges of the n-th recorded listening session.
+    fn session(
+        &self,
+        session: usize,
+        trigger: Trigger,
+    ) -> Option<(Duration, Vec<(Duration, Level)>)> {
+        let start = self
+            .entries
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, record))| *record == Record::Armed)
+            .nth(session)
+            .map(|(index, _)| index)?;
+
+        let edges = self.entries[start + 1..]
+            .iter()
+            .take_while(|(_, record)| *record != Record::Disarmed)
+            .filter_map(|(at, record)| match record {
+                Record::Irq(level) => Some((*at, *level)),
+                _ => None,
+            })
+            .filter(|(_, level)| match trigger {
+                Trigger::RisingEdge => *level == Level::High,
+                Trigger::FallingEdge => *level == Level::Low,
+                _ => true,
+            })
+            .collect();
+
+        Some((self.entries[start].0, edges))
+    }
+}
+
+impl IrqSource for ReplayIrqSource {
+    fn set_async_interrupt(
+        &mut self,
+        trigger: Trigger,
+        mut callback: IrqCallback,
+    ) -> crate::Result<()> {
+        self.clear_async_interrupt()?;
+
+        let (armed_at, edges) = self
+            .session(self.sessions, trigger)
+            .unwrap_or((Duration::from_secs(0), Vec::new()));
+        self.sessions += 1;
+
+        let speed = self.speed;
+        let (stop, stopped) = channel::<()>();
+
+        let handle = thread::spawn(move || {
+            let started_at = Instant::now();
+
+            for (at, level) in edges {
+                let delay = match speed {
+                    ReplaySpeed::RealTime => {
+                        // a hand-edited recording may not be monotonic
+                        (started_at + at.saturating_sub(armed_at))
+                            .saturating_duration_since(Instant::now())
+                    }
+                    ReplaySpeed::AsFastAsPossible => Duration::from_secs(0),
+                };
+
+                match stopped.recv_timeout(delay) {
+                    Err(RecvTimeoutError::Timeout) => callback(level),
+                    _ => return,
+                }
+            }
+
+            debug!("replay: no more IRQ edges in the session");
+        });
+
+        self.playback = Some((stop, handle));
+
+        Ok(())
+    }
+
+    fn clear_async_interrupt(&mut self) -> crate::Result<()> {
+        if let Some((stop, handle)) = self.playback.take() {
+            drop(stop);
+            let _ = handle.join();
+        }
+
+        Ok(())
+    }
+
+    fn pin(&self) -> Option<u8> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RECORDS: &[&str] = &[
+        "B i2c 1 3",
+        "B spi 0 1",
+        "R 03 0f 08",
+        "R 07 3f !i2c error message",
+        "W 00 01 00",
+        "W 08 0f 05 !spi failure",
+        "A",
+        "D",
+        "I 0",
+        "I 1",
+        "E lightning 12 3456",
+        "E lightning out_of_range 0",
+        "E lightning overhead 100",
+        "E disturbance",
+        "E noise",
+        "E status sensor_reset",
+        "E status sensor_unavailable",
+        "E status recalibrated 1 0",
+    ];
+
+    fn parse(line: &str) -> ::std::result::Result<Record, String> {
+        Record::parse(&line.split_whitespace().collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn records_round_trip_through_their_text_form() {
+        for &line in RECORDS {
+            let record = parse(line).unwrap();
+            assert_eq!(record.to_string(), line);
+            assert_eq!(parse(&record.to_string()), Ok(record));
+        }
+    }
+
+    #[test]
+    fn rejects_numbers_that_do_not_fit_their_field() {
+        assert!(parse("B i2c 257 3").is_err());
+        assert!(parse("B spi 0 300").is_err());
+        assert!(parse("E lightning 256 3456").is_err());
+        assert!(parse("R 103 0f 08").is_err());
+        assert!(parse("B i2c one 3").is_err());
+    }
+
+    #[test]
+    fn parses_a_recording() {
+        let text = "as3935-recording 1\nB i2c 1 3\n1520 W 00 01 00\n\n9502 E lightning 12 3456\n";
+        let recording = Recording::parse(text.as_bytes()).unwrap();
+
+        assert_eq!(recording.bus(), Bus::I2c { bus: 1, address: 3 });
+        assert_eq!(
+            recording.events(),
+            vec![(
+                Duration::from_micros(9502),
+                Event::Lightning(HeadOfStormDistance::Kilometers(12), LightningEnergy(3456))
+            )]
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_recordings() {
+        match Recording::parse("as3935-recording 2\n".as_bytes()) {
+            Err(Error::UnsupportedVersion(2)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+        match Recording::parse("as3935-recording 1\nB i2c 1 3\n10 X\n".as_bytes()) {
+            Err(Error::Parse { line: 3, .. }) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+        match Recording::parse("as3935-recording 1\n10 A\n".as_bytes()) {
+            Err(Error::Parse { line: 2, .. }) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use crate::device::registers::{Mode, Register};
+use crate::interface::{self, Interface};
+use crate::AS3935;
+use std::thread::sleep;
+use std::time::Duration;
+
+pub const DEFAULT_RETRY_ATTEMPTS: u32 = 3;
+pub const DEFAULT_RETRY_BACKOFF: Duration = Duration::from_millis(10);
+
+/// How often a failed bus access is attempted again, see [`AS3935::with_retry_policy`].
+///
+/// The backoff doubles after every attempt. Direct commands are attempted once, as repeating them could e.g.
+/// restart an oscillators calibration, and so is any access to the address of the interrupt register, as a
+/// failed read, including the one of a read-modify-write, may have cleared it already; the IRQ handler
+/// re-applies the configuration instead.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RetryPolicy {
+    attempts: u32,
+    backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::new(DEFAULT_RETRY_ATTEMPTS, DEFAULT_RETRY_BACKOFF)
+    }
+}
+
+impl RetryPolicy {
+    /// `attempts` includes the first one.
+    pub fn new(attempts: u32, backoff: Duration) -> Self {
+        Self {
+            attempts: attempts.max(1),
+            backoff,
+        }
+    }
+
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    pub fn backoff(&self) -> Duration {
+        self.backoff
+    }
+}
+
+impl AS3935 {
+    /// Attempts failed bus accesses again according to `policy`.
+    ///
+    /// Should reading an event fail even so, the sensor is probed and its configuration re-applied, as without a
+    /// policy, and if that fails too, a [`Status::SensorUnavailable`](crate::Status::SensorUnavailable) is reported.
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.decorate_interface(move |interface| Box::new(RetryingInterface { interface, policy }));
+        self
+    }
+}
+
+struct RetryingInterface {
+    interface: Box<dyn Interface>,
+    policy: RetryPolicy,
+}
+
+impl RetryingInterface {
+    fn attempt<T, F>(&mut self, register: &RegisterCopy, mut access: F) -> interface::Result<T>
+    where
+        F: FnMut(&mut Box<dyn Interface>, Box<dyn Register>) -> interface::Result<T>,
+    {
+        let mut backoff = self.policy.backoff;
+        let mut attempt = 1;
+
+        loop {
+            match access(&mut self.interface, Box::new(*register)) {
+                Ok(value) => return Ok(value),
+                Err(e) if attempt < self.policy.attempts => {
+                    warn!(
+                        "accessing {} failed (attempt {} of {}): {}",
+                        register.name, attempt, self.policy.attempts, e
+                    );
+
+                    sleep(backoff);
+                    backoff *= 2;
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+impl Interface for RetryingInterface {
+    fn read(&mut self, register: Box<dyn Register>) -> interface::Result<u8> {
+        if register.clears_on_read() {
+            return self.interface.read(register);
+        }
+
+        let register = RegisterCopy::from(register);
+
+        self.attempt(&register, |interface, register| interface.read(register))
+    }
+
+    fn write(&mut self, register: Box<dyn Register>, payload: u8) -> interface::Result<()> {
+        if register.is_direct_command() || register.clears_on_read() {
+            return self.interface.write(register, payload);
+        }
+
+        let register = RegisterCopy::from(register);
+
+        self.attempt(&register, |interface, register| {
+            interface.write(register, payload)
+        })
+    }
+}
+
+/// Registers are boxed and consumed by every access, the copy stands in for them on each attempt.
+#[derive(Clone, Copy)]
+struct RegisterCopy {
+    name: &'static str,
+    description: &'static str,
+    address: u8,
+    mode: Mode,
+    mask: u8,
+    default_value: u8,
+}
+
+impl From<Box<dyn Register>> for RegisterCopy {
+    fn from(register: Box<dyn Register>) -> Self {
+        Self {
+            name: register.name(),
+            description: register.description(),
+            address: register.address(),
+            mode: register.mode(),
+            mask: register.mask(),
+            default_value: register.default_value(),
+        }
+    }
+}
+
+impl Register for RegisterCopy {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn description(&self) -> &'static str {
+        self.description
+    }
+
+    fn address(&self) -> u8 {
+        self.address
+    }
+
+    fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    fn mask(&self) -> u8 {
+        self.mask
+    }
+
+    fn default_value(&self) -> u8 {
+        self.default_value
+    }
+}
@@ -0,0 +1,296 @@
+use crate::{
+    Error, Event, IgnoreDisturbances, ListeningParameters, NoiseFloorThreshold, Result,
+    SensorPlacing, SignalVerificationThreshold, SpikeRejectionThreshold, AS3935,
+};
+use std::sync::mpsc::RecvTimeoutError;
+use std::time::{Duration, Instant};
+
+/// Noise interrupts per minute up to which a noise floor threshold is recommended.
+pub const ACCEPTABLE_NOISE_RATE: f64 = 0.5;
+/// Disturbers per minute up to which a signal verification or spike rejection threshold is recommended.
+pub const ACCEPTABLE_DISTURBER_RATE: f64 = 1.0;
+
+/// Shortest listening time of a step, the rates of shorter steps are meaningless.
+pub const MINIMUM_DWELL: Duration = Duration::from_secs(1);
+
+/// Parameter varied by a part of the schedule, the others stay at their defaults.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Sweep {
+    NoiseFloor,
+    SignalVerification,
+    SpikeRejection,
+}
+
+/// Settings the survey goes through: NF_LEV, WDTH and SREJ are swept one at a time, for each placement.
+fn schedule() -> Vec<(SensorPlacing, Sweep, ListeningParameters)> {
+    let mut schedule = Vec::new();
+
+    for &placing in &[SensorPlacing::Indoor, SensorPlacing::Outdoor] {
+        let defaults = ListeningParameters::default()
+            .with_sensor_placing(placing)
+            .with_noise_floor_threshold(NoiseFloorThreshold(2))
+            .with_signal_verification_threshold(SignalVerificationThreshold(2))
+            .with_spike_rejection_threshold(SpikeRejectionThreshold(2))
+            .with_ignore_disturbances(IgnoreDisturbances::No);
+
+        for value in 0..=7 {
+            let parameters = defaults.with_noise_floor_threshold(NoiseFloorThreshold(value));
+            schedule.push((placing, Sweep::NoiseFloor, parameters));
+        }
+
+        for value in (0..=10).step_by(2) {
+            let parameters =
+                defaults.with_signal_verification_threshold(SignalVerificationThreshold(value));
+            schedule.push((placing, Sweep::SignalVerification, parameters));
+        }
+
+        for value in (0..=14).step_by(2) {
+            let parameters =
+                defaults.with_spike_rejection_threshold(SpikeRejectionThreshold(value));
+            schedule.push((placing, Sweep::SpikeRejection, parameters));
+        }
+    }
+
+    schedule
+}
+
+/// Events counted while listening with one setting of the schedule.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SurveyStep {
+    pub parameters: ListeningParameters,
+    pub duration: Duration,
+    pub noise: u32,
+    pub disturbers: u32,
+    pub lightning: u32,
+}
+
+impl SurveyStep {
+    /// Noise interrupts per minute.
+    pub fn noise_rate(&self) -> f64 {
+        f64::from(self.noise) * 60.0 / self.duration.as_secs_f64()
+    }
+
+    /// Disturbers per minute.
+    pub fn disturber_rate(&self) -> f64 {
+        f64::from(self.disturbers) * 60.0 / self.duration.as_secs_f64()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct SurveyReport {
+    /// AFE gain, noise floor, signal verification and spike rejection thresholds to listen with.
+    pub recommendation: ListeningParameters,
+    /// Steps the recommended noise floor, signal verification and spike rejection thresholds were chosen by.
+    pub evidence: Vec<SurveyStep>,
+    /// Whether the rates of the evidence are acceptable, otherwise the most robust settings were recommended.
+    pub acceptable: bool,
+    pub steps: Vec<SurveyStep>,
+}
+
+/// Site survey which listens with a schedule of settings and recommends the listening parameters, see
+/// [`AS3935::resume_survey`].
+///
+/// The survey keeps the completed steps, so that an interrupted one can be resumed, also after saving it with
+/// the `serde` feature.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Survey {
+    /// Listening time of each step.
+    dwell: Duration,
+    steps: Vec<SurveyStep>,
+}
+
+impl Survey {
+    /// Spreads `duration` evenly over the steps of the schedule.
+    ///
+    /// Fails with [`Error::InvalidArgument`] if a step would listen for less than [`MINIMUM_DWELL`].
+    pub fn new(duration: Duration) -> Result<Self> {
+        let dwell = duration / schedule().len() as u32;
+
+        if dwell < MINIMUM_DWELL {
+            return Err(Error::InvalidArgument(
+                "survey must last at least a second per step of the schedule",
+            ));
+        }
+
+        Ok(Self {
+            dwell,
+            steps: Vec::new(),
+        })
+    }
+
+    /// Listening time of each step.
+    pub fn dwell(&self) -> Duration {
+        self.dwell
+    }
+
+    pub fn completed(&self) -> usize {
+        self.steps.len()
+    }
+
+    pub fn total(&self) -> usize {
+        schedule().len()
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.completed() >= self.total()
+    }
+
+    /// Time left until the survey completes.
+    pub fn remaining(&self) -> Duration {
+        self.dwell * (self.total() - self.completed().min(self.total())) as u32
+    }
+
+    pub fn steps(&self) -> &[SurveyStep] {
+        &self.steps
+    }
+
+    /// Recommends the listening parameters once the survey is complete.
+    ///
+    /// For each placement, the lowest thresholds with acceptable rates are picked. The indoor gain is
+    /// recommended if all its rates are acceptable, the outdoor one otherwise.
+    pub fn report(&self) -> Option<SurveyReport> {
+        if !self.is_complete() {
+            return None;
+        }
+
+        let schedule = schedule();
+        let pick = |placing: SensorPlacing| {
+            let lowest_acceptable = |sweep: Sweep, acceptable: &dyn Fn(&SurveyStep) -> bool| {
+                let swept: Vec<&SurveyStep> = schedule
+                    .iter()
+                    .zip(&self.steps)
+                    .filter(|((p, s, _), _)| *p == placing && *s == sweep)
+                    .map(|(_, step)| step)
+                    .collect();
+
+                match swept.iter().find(|step| acceptable(step)) {
+                    Some(step) => (**step, true),
+                    None => (**swept.last().unwrap(), false),
+                }
+            };
+
+            let noise_floor = lowest_acceptable(Sweep::NoiseFloor, &|step| {
+                step.noise_rate() <= ACCEPTABLE_NOISE_RATE
+            });
+            let signal_verification = lowest_acceptable(Sweep::SignalVerification, &|step| {
+                step.disturber_rate() <= ACCEPTABLE_DISTURBER_RATE
+            });
+            let spike_rejection = lowest_acceptable(Sweep::SpikeRejection, &|step| {
+                step.disturber_rate() <= ACCEPTABLE_DISTURBER_RATE
+            });
+
+            (
+                vec![noise_floor.0, signal_verification.0, spike_rejection.0],
+                noise_floor.1 && signal_verification.1 && spike_rejection.1,
+            )
+        };
+
+        let (evidence, acceptable) = match pick(SensorPlacing::Indoor) {
+            (evidence, true) => (evidence, true),
+            _ => pick(SensorPlacing::Outdoor),
+        };
+
+        let recommendation = ListeningParameters {
+            afe_gain: evidence[0].parameters.afe_gain,
+            noise_floor_threshold: evidence[0].parameters.noise_floor_threshold,
+            signal_verification_threshold: evidence[1].parameters.signal_verification_threshold,
+            spike_rejection_threshold: evidence[2].parameters.spike_rejection_threshold,
+            ..ListeningParameters::default()
+        };
+
+        Some(SurveyReport {
+            recommendation,
+            evidence,
+            acceptable,
+            steps: self.steps.clone(),
+        })
+    }
+}
+
+impl AS3935 {
+    /// Surveys the site for `duration` and recommends the listening parameters, logging the progress.
+    pub fn survey(&mut self, duration: Duration) -> Result<SurveyReport> {
+        self.resume_survey(&mut Survey::new(duration)?, |survey| {
+            info!(
+                "survey step {} of {} done, {} s remaining",
+                survey.completed(),
+                survey.total(),
+                survey.remaining().as_secs()
+            );
+        })
+    }
+
+    /// Runs the remaining steps of `survey` and recommends the listening parameters.
+    ///
+    /// `progress` is called after every step, e.g. to report the progress or to save the survey. Should a step
+    /// fail, the survey keeps the completed ones and can be resumed. The sensor is left standing by, configured
+    /// with the last step; other parameters, such as the tuning capacitors, are kept.
+    pub fn resume_survey<F: FnMut(&Survey)>(
+        &mut self,
+        survey: &mut Survey,
+        mut progress: F,
+    ) -> Result<SurveyReport> {
+        for (_, _, parameters) in schedule().into_iter().skip(survey.completed()) {
+            debug!("surveying with {:?}", parameters);
+
+            let step = self.survey_step(parameters, survey.dwell)?;
+            survey.steps.push(step);
+
+            progress(survey);
+        }
+
+        Ok(survey.report().unwrap())
+    }
+
+    fn survey_step(
+        &mut self,
+        parameters: ListeningParameters,
+        dwell: Duration,
+    ) -> Result<SurveyStep> {
+        let events = self.listen(self.parameters().merge(parameters))?;
+        let started = Instant::now();
+
+        let mut step = SurveyStep {
+            parameters,
+            duration: dwell,
+            noise: 0,
+            disturbers: 0,
+            lightning: 0,
+        };
+
+        while let Some(remaining) = dwell.checked_sub(started.elapsed()) {
+            match events.recv_timeout(remaining) {
+                Ok(Event::Noise) => step.noise += 1,
+                Ok(Event::Disturbance) => step.disturbers += 1,
+                Ok(Event::Lightning(..)) => step.lightning += 1,
+                Ok(Event::Status(status)) => warn!("sensor status during survey: {:?}", status),
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+
+        self.stop_listening()?;
+
+        Ok(step)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_surveys_shorter_than_a_second_per_step() {
+        let steps = schedule().len() as u32;
+
+        assert!(Survey::new(Duration::from_secs(0)).is_err());
+        assert!(Survey::new(MINIMUM_DWELL * steps - Duration::from_millis(1)).is_err());
+        assert_eq!(
+            Survey::new(MINIMUM_DWELL * steps).unwrap().dwell(),
+            MINIMUM_DWELL
+        );
+    }
+}
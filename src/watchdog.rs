@@ -0,0 +1,233 @@
+use crate::broadcast::Subscribers;
+use crate::device::registers::{
+    AfeGainBoost, InternalTuningCapacitors, MaskDisturber, MinimumNumberOfLightning,
+    NoiseFloorLevel, PowerDown, Register, SpikeRejection, TrcoCalibrationDone, WatchdogThreshold,
+};
+use crate::interface::SharedInterface;
+use crate::metrics::Metrics;
+use crate::{calibrate_oscillators, Event, IrqMask, ListeningParameters, Result, Status, AS3935};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread::{self, sleep, JoinHandle};
+use std::time::Duration;
+
+pub const DEFAULT_WATCHDOG_INTERVAL: Duration = Duration::from_secs(60);
+
+impl AS3935 {
+    /// While listening, reads the configuration back every `interval` and re-applies it if the sensor lost it,
+    /// e.g. due to a brownout. Every repair is reported as [`Status::SensorReset`].
+    pub fn with_config_watchdog(mut self, interval: Duration) -> Self {
+        self.watchdog = Some(ConfigWatchdog {
+            interval,
+            running: None,
+        });
+        self
+    }
+}
+
+impl ListeningParameters {
+    /// Registers of the parameters which are set, with their raw values.
+    pub(crate) fn registers(&self) -> Vec<(Box<dyn Register>, u8)> {
+        let mut registers: Vec<(Box<dyn Register>, u8)> = Vec::new();
+
+        if let Some(afe_gain) = self.afe_gain {
+            registers.push((Box::new(AfeGainBoost), afe_gain.into()));
+        }
+
+        if let Some(minimum_lightning_threshold) = self.minimum_lightning_threshold {
+            registers.push((
+                Box::new(MinimumNumberOfLightning),
+                minimum_lightning_threshold.into(),
+            ));
+        }
+
+        if let Some(noise_floor_threshold) = self.noise_floor_threshold {
+            registers.push((Box::new(NoiseFloorLevel), noise_floor_threshold.into()));
+        }
+
+        if let Some(signal_verification_threshold) = self.signal_verification_threshold {
+            registers.push((
+                Box::new(WatchdogThreshold),
+                signal_verification_threshold.into(),
+            ));
+        }
+
+        if let Some(spike_rejection_threshold) = self.spike_rejection_threshold {
+            registers.push((Box::new(SpikeRejection), spike_rejection_threshold.into()));
+        }
+
+        if let Some(ignore_disturbances) = self.ignore_disturbances {
+            registers.push((Box::new(MaskDisturber), ignore_disturbances.into()));
+        }
+
+        if let Some(tuning_capacitors) = self.tuning_capacitors {
+            registers.push((Box::new(InternalTuningCapacitors), tuning_capacitors.into()));
+        }
+
+        registers
+    }
+}
+
+/// State shared by the sensor with the watchdog and recalibration threads.
+#[derive(Clone)]
+pub(crate) struct WatchdogContext {
+    pub(crate) interface: Arc<SharedInterface>,
+    pub(crate) irq_mask: Arc<IrqMask>,
+    pub(crate) metrics: Option<Arc<Metrics>>,
+    pub(crate) parameters: Arc<Mutex<ListeningParameters>>,
+    pub(crate) subscribers: Arc<Subscribers>,
+}
+
+pub(crate) struct ConfigWatchdog {
+    interval: Duration,
+    running: Option<Running>,
+}
+
+struct Running {
+    /// Cleared while the sensor sleeps, as it is powered down on purpose.
+    active: Arc<AtomicBool>,
+    stop: Sender<()>,
+    thread: JoinHandle<()>,
+}
+
+impl ConfigWatchdog {
+    pub(crate) fn start(&mut self, context: WatchdogContext) {
+        self.stop();
+
+        let active = Arc::new(AtomicBool::new(true));
+        let (stop, stopped) = channel::<()>();
+        let interval = self.interval;
+
+        let thread = {
+            let active = active.clone();
+            let mut available = true;
+
+            thread::spawn(move || loop {
+                match stopped.recv_timeout(interval) {
+                    Err(RecvTimeoutError::Timeout) => {}
+                    _ => return,
+                }
+
+                if !active.load(Ordering::SeqCst) {
+                    continue;
+                }
+
+                let parameters = *context
+                    .parameters
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner);
+
+                match repair(&context.interface, &context.irq_mask, &parameters, false) {
+                    Ok(false) => available = true,
+                    Ok(true) => {
+                        available = true;
+
+                        if let Some(metrics) = &context.metrics {
+                            metrics.observe_status(&Status::SensorReset);
+                        }
+
+                        context
+                            .subscribers
+                            .publish(Event::Status(Status::SensorReset));
+                    }
+                    Err(e) => {
+                        warn!("failed to verify the sensor configuration: {}", e);
+
+                        // reported once until the sensor responds again
+                        if available {
+                            available = false;
+                            if let Some(metrics) = &context.metrics {
+                                metrics.observe_status(&Status::SensorUnavailable);
+                            }
+                            context
+                                .subscribers
+                                .publish(Event::Status(Status::SensorUnavailable));
+                        }
+                    }
+                }
+            })
+        };
+
+        self.running = Some(Running {
+            active,
+            stop,
+            thread,
+        });
+    }
+
+    pub(crate) fn set_active(&self, active: bool) {
+        if let Some(running) = &self.running {
+            running.active.store(active, Ordering::SeqCst);
+        }
+    }
+
+    pub(crate) fn stop(&mut self) {
+        if let Some(running) = self.running.take() {
+            drop(running.stop);
+
+            if running.thread.join().is_err() {
+                error!("configuration watchdog thread panicked");
+            }
+        }
+    }
+}
+
+/// Re-applies the parameters if any of them drifted or the sensor powered down, or regardless of that when
+/// `force` is set, returns whether it did so.
+pub(crate) fn repair(
+    interface: &SharedInterface,
+    irq_mask: &IrqMask,
+    parameters: &ListeningParameters,
+    force: bool,
+) -> Result<bool> {
+    let (powered_down, calibrated, drifted) = {
+        let mut interface = interface.lock()?;
+
+        let powered_down = interface.read(Box::new(PowerDown))? == 0b_1;
+        // the calibration results are cleared when the registers are reset to defaults
+        let calibrated = interface.read(Box::new(TrcoCalibrationDone))? == 0b_1;
+
+        let mut drifted = Vec::new();
+        for (register, value) in parameters.registers() {
+            let name = register.name();
+
+            if interface.read(register)? != value {
+                drifted.push(name);
+            }
+        }
+
+        (powered_down, calibrated, drifted)
+    };
+
+    if !powered_down && calibrated && drifted.is_empty() {
+        if !force {
+            return Ok(false);
+        }
+
+        info!("re-applying the sensor configuration");
+    } else {
+        warn!(
+            "sensor configuration drifted (powered down: {}, calibrated: {}, drifted: {}), re-applying it",
+            powered_down,
+            calibrated,
+            drifted.join(", ")
+        );
+    }
+
+    if powered_down {
+        interface.lock()?.write(Box::new(PowerDown), 0b_0)?;
+        sleep(Duration::from_millis(2));
+    }
+
+    if powered_down || !calibrated {
+        calibrate_oscillators(interface, irq_mask)?;
+    }
+
+    let mut interface = interface.lock()?;
+    for (register, value) in parameters.registers() {
+        interface.write(register, value)?;
+    }
+
+    Ok(true)
+}